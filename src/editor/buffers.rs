@@ -0,0 +1,86 @@
+// Files open alongside the one on screen, each keeping its own cursor and changes
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::{
+  cursor::CursorController,
+  editor::EditorRows,
+  highlight::SyntaxHighlight,
+};
+
+pub struct Buffer {
+  pub editor_rows: EditorRows,
+  pub cursor_controller: CursorController,
+  pub syntax_highlight: Option<Box<dyn SyntaxHighlight>>,
+  pub dirty: bool,
+}
+
+pub struct Buffers {
+  // The current buffer's slot is empty while `Output` holds it
+  list: Vec<Option<Buffer>>,
+  pub current: usize,
+}
+
+impl Buffers {
+  pub fn new() -> Self {
+    Self {
+      list: vec![None],
+      current: 0,
+    }
+  }
+
+  pub fn count(&self) -> usize {
+    self.list.len()
+  }
+
+  // Stores the current buffer and hands back the one at `index`
+  pub fn switch(&mut self, current: Buffer, index: usize) -> Buffer {
+    self.list[self.current] = Some(current);
+    self.current = index;
+    self.list[index].take().expect("Buffer is already in use.")
+  }
+
+  // Stores the current buffer and makes room for a new one at the end
+  pub fn push(&mut self, current: Buffer) {
+    self.list[self.current] = Some(current);
+    self.list.push(None);
+    self.current = self.list.len() - 1;
+  }
+
+  // Drops the current buffer's slot and hands back the one that takes its place,
+  // or `None` when it was the only one
+  pub fn close(&mut self) -> Option<Buffer> {
+    if self.list.len() == 1 {
+      return None;
+    }
+    self.list.remove(self.current);
+    self.current = self.current.min(self.list.len() - 1);
+    self.list[self.current].take()
+  }
+
+  pub fn position(&self, file: &Path) -> Option<usize> {
+    let file = canonical(file);
+    self.list.iter().position(|buffer| {
+      buffer
+        .as_ref()
+        .and_then(|buffer| buffer.editor_rows.filename.as_deref())
+        .is_some_and(|filename| canonical(filename) == file)
+    })
+  }
+
+  // Buffers other than the current one, with their numbers
+  pub fn others(&self) -> impl Iterator<Item = (usize, &Buffer)> {
+    self.list
+      .iter()
+      .enumerate()
+      .filter_map(|(index, buffer)| buffer.as_ref().map(|buffer| (index, buffer)))
+  }
+}
+
+pub fn same_file(a: &Path, b: &Path) -> bool {
+  canonical(a) == canonical(b)
+}
+
+fn canonical(file: &Path) -> PathBuf {
+  fs::canonicalize(file).unwrap_or_else(|_| file.to_path_buf())
+}
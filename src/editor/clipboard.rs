@@ -0,0 +1,57 @@
+use crate::log;
+
+// Wraps the system clipboard when the `clipboard` feature is enabled.
+// Every failure is logged and reported back so callers can fall back to
+// the internal register.
+pub struct Clipboard {
+  #[cfg(feature = "clipboard")]
+  system: Option<arboard::Clipboard>,
+}
+
+impl Clipboard {
+  pub fn new() -> Self {
+    #[cfg(feature = "clipboard")]
+    {
+      let system = arboard::Clipboard::new()
+        .map_err(|error| {
+          log::log::log("WARN".to_string(), format!("System clipboard unavailable: {}", error));
+        })
+        .ok();
+      Self { system }
+    }
+    #[cfg(not(feature = "clipboard"))]
+    {
+      Self {}
+    }
+  }
+
+  pub fn set(&mut self, text: &str) -> bool {
+    #[cfg(feature = "clipboard")]
+    if let Some(system) = self.system.as_mut() {
+      return match system.set_text(text) {
+        Ok(_) => true,
+        Err(error) => {
+          log::log::log("WARN".to_string(), format!("Unable to copy to clipboard: {}", error));
+          false
+        },
+      };
+    }
+    log::log::log("INFO".to_string(), format!("No system clipboard, kept {} bytes in the register.", text.len()));
+    false
+  }
+
+  pub fn get(&mut self) -> Option<String> {
+    #[cfg(feature = "clipboard")]
+    if let Some(system) = self.system.as_mut() {
+      return match system.get_text() {
+        Ok(text) => Some(text),
+        Err(error) => {
+          log::log::log("WARN".to_string(), format!("Unable to paste from clipboard: {}", error));
+          None
+        },
+      };
+    }
+    log::log::log("INFO".to_string(), "No system clipboard, falling back to the register.".to_string());
+    None
+  }
+}
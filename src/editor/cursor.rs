@@ -1,8 +1,14 @@
 use std::cmp;
 use crossterm::event::KeyCode;
 use crate::editor::editor::{EditorRows, Row};
-use crate::CONFIG;
+use crate::editor::text::{self, CharClass};
 
+// A screen line showing render columns start..end of a file row, and whether it's
+// the row's first line, the one that gets the line number
+pub type ScreenLine = (usize, usize, usize, bool); // file_row, start, end, first
+
+// The cursor always sits on a row of the file: `cursor_y` stays below `number_of_rows()`,
+// except in an empty buffer where it is 0 and typing there adds the first row
 #[derive(Copy, Clone)]
 pub struct CursorController {
   pub cursor_x: usize,
@@ -20,26 +26,37 @@ impl CursorController {
       cursor_x: 0,
       cursor_y: 0,
       screen_columns: window_size.0,
-      screen_rows: window_size.1,
+      // Scrolling needs a line to keep the cursor on, even in a terminal too short to show it
+      screen_rows: cmp::max(window_size.1, 1),
       row_offset: 0,
       column_offset: 0,
       render_x: 0,
     }
   }
 
+  // The last row the cursor can be on, 0 for an empty buffer
+  fn last_row(editor_rows: &EditorRows) -> usize {
+    editor_rows.number_of_rows().saturating_sub(1)
+  }
+
+  // Pulls the cursor back inside the text, for when it was moved by edits elsewhere
+  pub fn clamp(&mut self, editor_rows: &EditorRows) {
+    let number_of_rows = editor_rows.number_of_rows();
+    self.cursor_y = cmp::min(self.cursor_y, Self::last_row(editor_rows));
+    let row_length = if self.cursor_y < number_of_rows {
+      editor_rows.get_editor_row(self.cursor_y).char_count()
+    } else {
+      0
+    };
+    self.cursor_x = cmp::min(self.cursor_x, row_length);
+  }
+
+  // Column of the cursor within the row's text, the gutter is added when drawing
   pub fn get_render_x(&self, row: &Row) -> usize {
-    row.row_content[..self.cursor_x]
-      .chars()
-      .fold(4, |render_x, c| {
-        if c == '\t' {
-          render_x + (CONFIG.spaces_per_tab - 1) - (render_x % CONFIG.spaces_per_tab) + 1
-        } else {
-          render_x + 1
-        }
-      })
+    row.render_column(self.cursor_x)
   }
 
-  pub fn scroll(&mut self, editor_rows: &EditorRows) {
+  pub fn scroll(&mut self, editor_rows: &EditorRows, wrap: bool) {
     self.render_x = 0;
     if self.cursor_y < editor_rows.number_of_rows() {
       self.render_x = self.get_render_x(editor_rows.get_editor_row(self.cursor_y));
@@ -50,12 +67,233 @@ impl CursorController {
       self.row_offset = self.cursor_y - self.screen_rows + 1;
     }
 
+    let text_columns = self.text_columns(editor_rows);
+    if wrap {
+      // Nothing scrolls sideways, rows scroll off the top until the cursor's screen line fits
+      self.column_offset = 0;
+      while self.row_offset < self.cursor_y && self.wrapped_position(editor_rows, text_columns).1 >= self.screen_rows {
+        self.row_offset += 1;
+      }
+      return;
+    }
     self.column_offset = cmp::min(self.column_offset, self.render_x);
-    if self.render_x >= self.column_offset + self.screen_columns {
-      self.column_offset = self.render_x - self.screen_columns + 1;
+    if self.render_x >= self.column_offset + text_columns {
+      self.column_offset = self.render_x - text_columns + 1;
+    }
+  }
+
+  // Columns left for text beside the gutter
+  pub fn text_columns(&self, editor_rows: &EditorRows) -> usize {
+    cmp::max(self.screen_columns.saturating_sub(editor_rows.gutter_width()), 1)
+  }
+
+  fn row_height(row: &Row, text_columns: usize) -> usize {
+    cmp::max(text::width(&row.render).div_ceil(text_columns), 1)
+  }
+
+  // The cursor's row gets another line when the cursor is just past a row that fills
+  // its last line exactly, since the cursor starts the next line rather than overhanging
+  fn screen_height(&self, editor_rows: &EditorRows, at: usize, text_columns: usize) -> usize {
+    let height = Self::row_height(editor_rows.get_editor_row(at), text_columns);
+    if at == self.cursor_y {
+      cmp::max(height, self.render_x / text_columns + 1)
+    } else {
+      height
     }
   }
 
+  // The cursor's column and screen line when long rows wrap
+  fn wrapped_position(&self, editor_rows: &EditorRows, text_columns: usize) -> (usize, usize) {
+    let number_of_rows = editor_rows.number_of_rows();
+    let lines_above: usize = (self.row_offset..cmp::min(self.cursor_y, number_of_rows))
+      .map(|at| Self::row_height(editor_rows.get_editor_row(at), text_columns))
+      .sum();
+    let height = if self.cursor_y < number_of_rows {
+      self.screen_height(editor_rows, self.cursor_y, text_columns)
+    } else {
+      1
+    };
+    let line = cmp::min(self.render_x / text_columns, height - 1);
+    (self.render_x - line * text_columns, lines_above + line)
+  }
+
+  // Where the cursor is drawn within the text area, as (column, screen line)
+  pub fn screen_position(&self, editor_rows: &EditorRows, wrap: bool) -> (usize, usize) {
+    if wrap {
+      self.wrapped_position(editor_rows, self.text_columns(editor_rows))
+    } else {
+      (self.render_x - self.column_offset, self.cursor_y - self.row_offset)
+    }
+  }
+
+  // What each line of the screen shows, `None` past the end of the file
+  pub fn screen_lines(&self, editor_rows: &EditorRows, wrap: bool) -> Vec<Option<ScreenLine>> {
+    let text_columns = self.text_columns(editor_rows);
+    let mut lines = Vec::with_capacity(self.screen_rows);
+    let mut file_row = self.row_offset;
+    while lines.len() < self.screen_rows {
+      if file_row >= editor_rows.number_of_rows() {
+        lines.push(None);
+      } else if wrap {
+        let height = self.screen_height(editor_rows, file_row, text_columns);
+        (0..height).for_each(|line| {
+          lines.push(Some((file_row, line * text_columns, (line + 1) * text_columns, line == 0)))
+        });
+      } else {
+        lines.push(Some((file_row, self.column_offset, self.column_offset + text_columns, true)));
+      }
+      file_row += 1;
+    }
+    lines.truncate(self.screen_rows);
+    lines
+  }
+
+  fn next_word_start(editor_rows: &EditorRows, mut position: (usize, usize)) -> (usize, usize) {
+    let class = CharClass::of(editor_rows.char_at(position));
+    while class != CharClass::Blank && CharClass::of(editor_rows.char_at(position)) == class {
+      match editor_rows.next_position(position) {
+        Some(next) => position = next,
+        None => return position,
+      }
+    }
+    while CharClass::of(editor_rows.char_at(position)) == CharClass::Blank {
+      match editor_rows.next_position(position) {
+        Some(next) => position = next,
+        None => break,
+      }
+    }
+    position
+  }
+
+  fn word_end(editor_rows: &EditorRows, position: (usize, usize)) -> (usize, usize) {
+    let Some(mut position) = editor_rows.next_position(position) else {
+      return position;
+    };
+    while CharClass::of(editor_rows.char_at(position)) == CharClass::Blank {
+      match editor_rows.next_position(position) {
+        Some(next) => position = next,
+        None => return position,
+      }
+    }
+    let class = CharClass::of(editor_rows.char_at(position));
+    while let Some(next) = editor_rows.next_position(position) {
+      if CharClass::of(editor_rows.char_at(next)) != class {
+        break;
+      }
+      position = next;
+    }
+    position
+  }
+
+  fn previous_word_start(editor_rows: &EditorRows, position: (usize, usize)) -> (usize, usize) {
+    let Some(mut position) = editor_rows.previous_position(position) else {
+      return position;
+    };
+    while CharClass::of(editor_rows.char_at(position)) == CharClass::Blank {
+      match editor_rows.previous_position(position) {
+        Some(previous) => position = previous,
+        None => return position,
+      }
+    }
+    let class = CharClass::of(editor_rows.char_at(position));
+    while let Some(previous) = editor_rows.previous_position(position) {
+      if CharClass::of(editor_rows.char_at(previous)) != class {
+        break;
+      }
+      position = previous;
+    }
+    position
+  }
+
+  // Moves to a clicked screen cell, clamping like the keyboard motions do
+  pub fn click(&mut self, column: usize, row: usize, editor_rows: &EditorRows, wrap: bool) {
+    if row >= self.screen_rows {
+      return;
+    }
+    // Clicks below the end of the file land on its last line
+    let Some((file_row, start, _, _)) = self
+      .screen_lines(editor_rows, wrap)
+      .into_iter()
+      .take(row + 1)
+      .flatten()
+      .last() else {
+      return;
+    };
+    self.cursor_y = file_row;
+    let editor_row = editor_rows.get_editor_row(self.cursor_y);
+    let render_x = column.saturating_sub(editor_rows.gutter_width()) + start;
+    self.cursor_x = if render_x >= editor_row.render_column(editor_row.char_count()) {
+      editor_row.char_count()
+    } else {
+      editor_row.get_row_content_x(render_x)
+    };
+  }
+
+  // Scrolls the view by `lines`, dragging the cursor along when it would leave the screen
+  pub fn scroll_lines(&mut self, lines: isize, editor_rows: &EditorRows) {
+    let number_of_rows = editor_rows.number_of_rows();
+    self.row_offset = cmp::min(
+      self.row_offset.saturating_add_signed(lines),
+      number_of_rows.saturating_sub(1),
+    );
+    self.cursor_y = self.cursor_y.clamp(
+      self.row_offset,
+      cmp::max(self.row_offset, self.row_offset + self.screen_rows.saturating_sub(1)),
+    );
+    self.cursor_y = cmp::min(self.cursor_y, Self::last_row(editor_rows));
+    let row_length = if self.cursor_y < number_of_rows {
+      editor_rows.get_editor_row(self.cursor_y).char_count()
+    } else {
+      0
+    };
+    self.cursor_x = cmp::min(self.cursor_x, row_length);
+  }
+
+  // `f`, `F`, `t` and `T`, staying on the current row. A repeated `t` or `T` looks past
+  // the character it's already stopped beside
+  pub fn find_in_line(&mut self, kind: char, target: char, count: usize, repeat: bool, editor_rows: &EditorRows) -> bool {
+    if self.cursor_y >= editor_rows.number_of_rows() {
+      return false;
+    }
+    let chars: Vec<char> = editor_rows.get_row(self.cursor_y).chars().collect();
+    let skip = if repeat && matches!(kind, 't' | 'T') { 2 } else { 1 };
+    let found = if kind.is_lowercase() {
+      (self.cursor_x + skip..chars.len())
+        .filter(|&x| chars[x] == target)
+        .nth(count - 1)
+    } else {
+      (0..self.cursor_x.saturating_sub(skip - 1))
+        .rev()
+        .filter(|&x| chars[x] == target)
+        .nth(count - 1)
+    };
+    let Some(x) = found else {
+      return false;
+    };
+    self.cursor_x = match kind {
+      't' => x - 1,
+      'T' => x + 1,
+      _ => x,
+    };
+    true
+  }
+
+  // Ctrl-D and Ctrl-U, moving the view and the cursor together by half a screen
+  pub fn scroll_half_page(&mut self, down: bool, editor_rows: &EditorRows) {
+    let lines = cmp::max(self.screen_rows / 2, 1);
+    let number_of_rows = editor_rows.number_of_rows();
+    if down {
+      // The view stops once the last line reaches the bottom
+      let last_offset = cmp::max(self.row_offset, number_of_rows.saturating_sub(self.screen_rows));
+      self.row_offset = cmp::min(self.row_offset + lines, last_offset);
+      self.cursor_y = cmp::min(self.cursor_y + lines, number_of_rows.saturating_sub(1));
+    } else {
+      self.row_offset = self.row_offset.saturating_sub(lines);
+      self.cursor_y = self.cursor_y.saturating_sub(lines);
+    }
+    self.clamp(editor_rows);
+  }
+
   pub fn move_cursor(&mut self, direction: KeyCode, editor_rows: &EditorRows) {
     let number_of_rows = editor_rows.number_of_rows();
     match direction {
@@ -63,7 +301,7 @@ impl CursorController {
         self.cursor_y = self.cursor_y.saturating_sub(1);
       }
       KeyCode::Down => {
-        if self.cursor_y < number_of_rows {
+        if self.cursor_y < Self::last_row(editor_rows) {
           self.cursor_y += 1;
         }
       }
@@ -72,14 +310,14 @@ impl CursorController {
           self.cursor_x -= 1;
         } else if self.cursor_y > 0 {
           self.cursor_y -= 1;
-          self.cursor_x = editor_rows.get_row(self.cursor_y).len();
+          self.cursor_x = editor_rows.get_editor_row(self.cursor_y).char_count();
         }
       }
       KeyCode::Right => {
         if self.cursor_y < number_of_rows {
-          match self.cursor_x.cmp(&editor_rows.get_row(self.cursor_y).len()) {
+          match self.cursor_x.cmp(&editor_rows.get_editor_row(self.cursor_y).char_count()) {
             cmp::Ordering::Less => self.cursor_x += 1,
-            cmp::Ordering::Equal => {
+            cmp::Ordering::Equal if self.cursor_y < Self::last_row(editor_rows) => {
               self.cursor_y += 1;
               self.cursor_x = 0;
             },
@@ -89,18 +327,94 @@ impl CursorController {
       }
       KeyCode::End => {
         if self.cursor_y < number_of_rows {
-          self.cursor_x = editor_rows.get_row(self.cursor_y).len();
+          self.cursor_x = editor_rows.get_editor_row(self.cursor_y).char_count();
         }
       }
       KeyCode::Home => self.cursor_x = 0,
+      KeyCode::Char(motion @ ('w' | 'b' | 'e')) => {
+        if self.cursor_y < number_of_rows {
+          let position = (self.cursor_y, self.cursor_x);
+          (self.cursor_y, self.cursor_x) = match motion {
+            'w' => Self::next_word_start(editor_rows, position),
+            'b' => Self::previous_word_start(editor_rows, position),
+            _ => Self::word_end(editor_rows, position),
+          };
+        }
+      }
       _ => unimplemented!("Invalid keypress"),
     }
 
     let row_length = if self.cursor_y < number_of_rows {
-      editor_rows.get_row(self.cursor_y).len()
+      editor_rows.get_editor_row(self.cursor_y).char_count()
     } else {
       0
     };
     self.cursor_x = cmp::min(self.cursor_x, row_length);
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn cursor() -> CursorController {
+    CursorController::new((80, 24))
+  }
+
+  #[test]
+  fn stays_put_in_an_empty_buffer() {
+    let editor_rows = EditorRows::empty();
+    let mut cursor = cursor();
+    for direction in [KeyCode::Down, KeyCode::Right, KeyCode::End, KeyCode::Up, KeyCode::Left, KeyCode::Char('w')] {
+      cursor.move_cursor(direction, &editor_rows);
+      assert_eq!((cursor.cursor_y, cursor.cursor_x), (0, 0), "{:?}", direction);
+    }
+    cursor.cursor_y = 3;
+    cursor.cursor_x = 5;
+    cursor.clamp(&editor_rows);
+    assert_eq!((cursor.cursor_y, cursor.cursor_x), (0, 0));
+    cursor.scroll(&editor_rows, false);
+    assert_eq!(cursor.render_x, 0);
+  }
+
+  #[test]
+  fn stays_on_the_only_row_of_a_single_line_buffer() {
+    let editor_rows = EditorRows::from_str("abc\n", &None);
+    assert_eq!(editor_rows.number_of_rows(), 1);
+    let mut cursor = cursor();
+    cursor.move_cursor(KeyCode::Down, &editor_rows);
+    assert_eq!((cursor.cursor_y, cursor.cursor_x), (0, 0));
+    (0..5).for_each(|_| cursor.move_cursor(KeyCode::Right, &editor_rows));
+    assert_eq!((cursor.cursor_y, cursor.cursor_x), (0, 3));
+    cursor.move_cursor(KeyCode::Down, &editor_rows);
+    assert_eq!((cursor.cursor_y, cursor.cursor_x), (0, 3));
+    cursor.move_cursor(KeyCode::Up, &editor_rows);
+    cursor.move_cursor(KeyCode::Home, &editor_rows);
+    cursor.move_cursor(KeyCode::Left, &editor_rows);
+    assert_eq!((cursor.cursor_y, cursor.cursor_x), (0, 0));
+    cursor.cursor_y = 1;
+    cursor.cursor_x = 9;
+    cursor.clamp(&editor_rows);
+    assert_eq!((cursor.cursor_y, cursor.cursor_x), (0, 3));
+  }
+
+  #[test]
+  fn wraps_to_the_next_line_past_a_row_that_fills_the_screen() {
+    // 80 columns less the 2 of the gutter leave 78 for text
+    let mut editor_rows = EditorRows::from_str(&format!("{}\nb\n", "a".repeat(78)), &None);
+    editor_rows.line_numbers = true;
+    let mut cursor = cursor();
+    cursor.cursor_x = 77;
+    cursor.scroll(&editor_rows, true);
+    assert_eq!(cursor.screen_position(&editor_rows, true), (77, 0));
+    assert_eq!(cursor.screen_lines(&editor_rows, true)[1], Some((1, 0, 78, true)));
+
+    // Just past the end, as in insert mode, the cursor starts a line of its own
+    cursor.cursor_x = 78;
+    cursor.scroll(&editor_rows, true);
+    assert_eq!(cursor.screen_position(&editor_rows, true), (0, 1));
+    let lines = cursor.screen_lines(&editor_rows, true);
+    assert_eq!(lines[1], Some((0, 78, 156, false)));
+    assert_eq!(lines[2], Some((1, 0, 78, true)));
+  }
+}
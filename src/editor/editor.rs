@@ -1,23 +1,29 @@
 use std::{
   cmp,
+  collections::HashMap,
   io,
   env,
   fs,
-  path::PathBuf,
-  time::{Duration, Instant},
+  iter,
+  ops::Range,
+  path::{Path, PathBuf},
+  time::{Duration, Instant, SystemTime},
 };
-use std::io::Write;
-use crossterm::{event, terminal, queue};
-use crossterm::event::{KeyCode, KeyEvent};
+use std::io::{IsTerminal, Read, Write};
+use crossterm::{cursor, event, terminal, queue};
+use crossterm::event::{Event, KeyCode, KeyEvent, MouseEventKind};
 use colored::{Colorize};
 
 use crate::{
   log, 
   prompt,
-  Reader,
   CONFIG,
 };
 use super::{
+  substitute::Substitution,
+  swap,
+  text,
+  theme,
   highlight::{
     SyntaxHighlight,
     HighlightType
@@ -25,16 +31,35 @@ use super::{
   output::Output
 };
 
+// Counts stop growing past this, or the number of rows when there are more, so a long
+// run of digits can't repeat a macro or `.` for hours
+const MAX_COUNT: usize = 10_000;
+
 pub enum EditorModes {
   Insert,
-  Command
+  Command,
+  Visual,
 }
 
 pub struct Editor {
-  pub reader: Reader,
   pub output: Output,
   pub mode: EditorModes,
   previous_command_keys: Vec<KeyCode>,
+  count: Option<usize>,
+  // Keys of the change in progress, and of the last finished one for `.` to replay
+  // along with the count they were given
+  change_keys: Option<(Vec<KeyCode>, Option<usize>)>,
+  last_change: (Vec<KeyCode>, Option<usize>),
+  // The last `f`, `F`, `t` or `T` and its character, for `;` and `,`
+  last_find: Option<(char, char)>,
+  // Keys recorded with `q`, the register being recorded into, and the registers
+  // being played back so a macro can't call itself forever
+  macros: HashMap<char, Vec<KeyEvent>>,
+  recording: Option<(char, Vec<KeyEvent>)>,
+  replaying: Vec<char>,
+  last_macro: Option<char>,
+  // Ctrl-W was pressed and the next key picks what happens to the panes
+  pane_pending: bool,
 }
 
 impl Editor {
@@ -45,18 +70,58 @@ impl Editor {
     queue!(
       io::stdout(),
       terminal::EnterAlternateScreen,
+      event::EnableMouseCapture,
     )?;
-    Ok(Self {
-      reader: Reader,
-      output: Output::new(),
+    let mut editor = Self::with_output(Output::new());
+    editor.recover_swap()?;
+    Ok(editor)
+  }
+
+  // An editor around an output that needn't be a terminal, for testing
+  pub fn with_output(output: Output) -> Self {
+    Self {
+      output,
       mode: EditorModes::Command,
       previous_command_keys: Vec::new(),
-    })
+      count: None,
+      change_keys: None,
+      last_change: (Vec::new(), None),
+      last_find: None,
+      macros: HashMap::new(),
+      recording: None,
+      replaying: Vec::new(),
+      last_macro: None,
+      pane_pending: false,
+    }
   }
 
+  // One pass of the main loop, ending after a key or after `CONFIG.poll_timeout` goes by
+  // without one, so the screen is redrawn even while idle and expired messages clear
   pub fn run(&mut self) -> crossterm::Result<bool> {
     self.output.refresh_screen()?;
-    self.process_keypress()
+    let running = self.process_keypress()?;
+    if running {
+      self.output.update_swap();
+    } else {
+      self.output.close_all_buffers();
+    }
+    Ok(running)
+  }
+
+  // Offers the swap file when it's newer than the file, as it is after a crash
+  fn recover_swap(&mut self) -> crossterm::Result<()> {
+    let Some(contents) = self.output.editor_rows.filename.as_deref().and_then(swap::recoverable) else {
+      return Ok(());
+    };
+    let answer = prompt!(&mut self.output, "Found a swap file with unsaved changes. Recover it? (y/n) {}");
+    if answer.is_some_and(|answer| answer.starts_with(['y', 'Y'])) {
+      self.output.recover(&contents);
+      self.output.status_message.set_message("Recovered from the swap file.".to_string());
+    } else {
+      self.output.remove_swap();
+      self.output.status_message.set_message("Swap file discarded.".to_string());
+    }
+    Ok(())
   }
 
   fn set_previous_key(&mut self, key: KeyCode) {
@@ -89,124 +154,275 @@ impl Editor {
     self.output.status_message.set_message(message);
   }
 
-  fn toggle_mode(&mut self) {
-    // This works well enough for only having two modes
-    self.mode = match self.mode {
-      EditorModes::Command =>{
-        self.output.status_message.set_message("[INSERT]".to_string());
-        EditorModes::Insert
-      },
-      EditorModes::Insert => {
-        self.output.status_message.set_message("[COMMAND]".to_string());
-        EditorModes::Command
-      },
-    }
+  fn set_mode(&mut self, mode: EditorModes) {
+    // Visual mode anchors its selection wherever the cursor was when it started
+    self.output.selection_anchor = match mode {
+      EditorModes::Visual => Some((
+        self.output.cursor_controller.cursor_y,
+        self.output.cursor_controller.cursor_x,
+      )),
+      _ => None,
+    };
+    let message = match mode {
+      EditorModes::Insert => "[INSERT]",
+      EditorModes::Command => "[COMMAND]",
+      EditorModes::Visual => "[VISUAL]",
+    };
+    self.output.status_message.set_message(message.to_string());
+    // A bar while typing text, like modern vim
+    self.output.cursor_style = match mode {
+      EditorModes::Insert => cursor::SetCursorStyle::SteadyBar,
+      _ => cursor::SetCursorStyle::SteadyBlock,
+    };
+    self.mode = mode;
   }
 
-  fn save(&mut self) -> crossterm::Result<bool> {
-    if matches!(self.output.editor_rows.filename, None) {
-      let prompt = prompt!(&mut self.output, "Save as: {}")
+  // Returns whether the file was written, `force` overrides read-only
+  fn save(&mut self, force: bool) -> crossterm::Result<bool> {
+    if self.output.options.readonly && !force {
+      self.output
+        .status_message
+        .set_message("E45: 'readonly' option is set (add ! to override)".into());
+      return Ok(false);
+    }
+    if self.output.editor_rows.filename.is_none() {
+      let prompt = prompt!(&mut self.output, "Save as: {}", max_length = CONFIG.max_new_filename_length)
         .map(|it| it.into());
 
       if prompt.is_none() {
         self.output
           .status_message
           .set_message("Save aborted".into());
-        return Ok(true);
+        return Ok(false);
       }
       prompt
         .as_ref()
-        .and_then(|path: &PathBuf| path.extension())
-        .and_then(|ext| ext.to_str())
-        .map(|ext| {
-          Output::select_syntax(ext).map(|syntax| {
+        .map(|path: &PathBuf| {
+          Output::select_syntax(path).map(|syntax| {
             let highlight = self.output.syntax_highlight.insert(syntax);
-            for i in 0..self.output.editor_rows.number_of_rows() {
-              highlight
-                .update_syntax(i, &mut self.output.editor_rows.row_contents)
-            }
+            highlight.update_all_syntax(&mut self.output.editor_rows.row_contents)
           })
         });
       self.output.editor_rows.filename = prompt;
     }
-    self.output.editor_rows.save()?;
+    if self.output.editor_rows.changed_on_disk() && !force {
+      let answer = prompt!(&mut self.output, "The file has changed on disk since reading it. Write anyway? (y/n) {}");
+      if !answer.is_some_and(|answer| answer.starts_with(['y', 'Y'])) {
+        self.output
+          .status_message
+          .set_message("Save aborted".into());
+        return Ok(false);
+      }
+    }
+    self.output.editor_rows.save(self.output.options.backup)?;
+    self.output.remove_swap();
+    self.output.remember_cursor();
     self.output.status_message.set_message("File saved.".to_string());
     self.output.dirty = false;
     Ok(true)
   }
 
+  // Whether the file was written, a failed write is shown rather than ending the session
+  fn write_file(&mut self, force: bool) -> bool {
+    match self.save(force) {
+      Ok(saved) => saved,
+      Err(error) => {
+        log::log::log("WARN".to_string(), format!("Failed to save: {}", error));
+        self.output.status_message.set_message(format!("Can't write the file: {}", error));
+        false
+      },
+    }
+  }
+
+  fn substitute_arguments(command: &str) -> Option<(bool, &str)> {
+    // `s/...` works on the cursor's line, `%s/...` on every line
+    let (whole_file, arguments) = match command.strip_prefix("%s") {
+      Some(arguments) => (true, arguments),
+      None => (false, command.strip_prefix('s')?),
+    };
+    let delimiter = arguments.chars().next()?;
+    (!delimiter.is_alphanumeric() && !delimiter.is_whitespace()).then_some((whole_file, arguments))
+  }
+
   fn process_command(&mut self) -> crossterm::Result<bool> {
     let command: String = self.previous_command_keys.iter().map(|key| match key {
       KeyCode::Char(ch) => ch,
       _ => unreachable!(),
     }).collect();
     log::log::log("INFO".to_string(), format!("Command: {}", command));
-    match command.as_str() {
-      ":w" => {
+    let keymap = &CONFIG.keymap;
+    self.output.command_history.push(command.strip_prefix(keymap.command).unwrap_or(&command));
+    let (write, quit, find) = (keymap.write.to_string(), keymap.quit.to_string(), keymap.find.to_string());
+    let force_write = format!("{}!", keymap.write);
+    let force_quit = format!("{}!", keymap.quit);
+    let write_quit = format!("{}{}", keymap.write, keymap.quit);
+    // Everything after the command character
+    match command.strip_prefix(keymap.command).unwrap_or(&command) {
+      line if line == write => {
         // Save the file
         log::log::log("INFO".to_string(), "Saving file.".to_string());
-        match self.save() {
-          Ok(_) => {
-            return Ok(true)
-          },
-          Err(_) => {
-            return Ok(false)
-          }
-        }
+        self.write_file(false);
+        return Ok(true);
       }
-      ":q" => {
+      line if line == force_write => {
+        // Save even when read-only
+        log::log::log("INFO".to_string(), "Force saving file.".to_string());
+        self.write_file(true);
+        return Ok(true);
+      },
+      line if line == quit => {
         // Attempt to quit
         log::log::log("INFO".to_string(), "Attempting to quit.".to_string());
-        if self.output.dirty {
+        // With the window split only the pane closes
+        if self.output.close_pane() {
+          return Ok(true);
+        }
+        if self.output.any_dirty() {
           log::log::log("INFO".to_string(), "File has unsaved changes.".to_string());
-          self.output.status_message.set_message("File has unsaved changes. Press :q! to exit without saving.".to_string());
+          let answer = prompt!(&mut self.output, "Unsaved changes. Quit anyway? (y/n) {}");
+          // Esc cancels the prompt and leaves no answer
+          if answer.is_some_and(|answer| answer.starts_with(['y', 'Y'])) {
+            return Ok(false);
+          }
+          self.output.status_message.set_message(format!("File has unsaved changes. Press {}{} to exit without saving.", keymap.command, force_quit));
           self.clear_previous_keys();
           return Ok(true);
         } else {
           return Ok(false);
         }
       },
-      ":q!" => {
+      line if line == force_quit => {
         // Force quit
         log::log::log("INFO".to_string(), "Force quitting.".to_string());
         return Ok(false);
       },
-      ":wq" => {
+      line if line == write_quit => {
         // Save then quit
         log::log::log("INFO".to_string(), "Saving file and quitting.".to_string());
-        match self.save() {
-          Ok(_) => {
-            return Ok(false)
-          },
-          Err(_) => {
-            return Ok(true)
-          }
-        }
+        // Stay open if the write was refused, aborted or failed, or another buffer
+        // still has changes
+        return Ok(!self.write_file(false) || self.output.warn_hidden_changes());
       },
-      ":f" => {
+      line if line == find => {
         // Find
         log::log::log("INFO".to_string(), "Finding.".to_string());
-        match self.output.find() {
-          Ok(_) => {
-            return Ok(true)
-          },
-          Err(_) => {
-            return Ok(false)
-          }
+        // Falls through so the `:f` is cleared, and a later command doesn't follow it
+        if self.output.find().is_err() {
+          return Ok(false);
         }
       },
-      ":o" => {
+      "o" => {
         log::log::log("INFO".to_string(), "Opening new line.".to_string());
         self.output.insert_newline();
       },
-      ":d" => {
+      "d" => {
         log::log::log("INFO".to_string(), "Deleting line.".to_string());
-        self.output.delete_line();
+        self.output.delete_lines(1);
       },
-      // ":u" => {
+      // "u" => {
       //   log::log::log("INFO".to_string(), "Undoing last action.".to_string());
       //   self.output.undo();
       // }
+      line if Self::substitute_arguments(line).is_some() => {
+        log::log::log("INFO".to_string(), format!("Substituting: {}", line));
+        let (whole_file, arguments) = Self::substitute_arguments(line).unwrap();
+        let rows = if whole_file {
+          0..self.output.editor_rows.number_of_rows()
+        } else {
+          let cursor_y = self.output.cursor_controller.cursor_y;
+          cursor_y..cmp::min(cursor_y + 1, self.output.editor_rows.number_of_rows())
+        };
+        // The pattern follows `:set regex`, `ignorecase` and `smartcase` like searches do
+        let parsed = Substitution::parse(arguments).and_then(|substitution| {
+          let pattern = self.output.search_pattern(&substitution.pattern)?;
+          Ok((substitution, pattern))
+        });
+        match parsed {
+          Ok(_) if !self.output.is_modifiable() => {},
+          Ok((substitution, pattern)) => {
+            let (replacements, lines) = if substitution.confirm {
+              self.output.substitute_confirm(&substitution, &pattern, rows)?
+            } else {
+              self.output.substitute(&substitution, &pattern, rows)
+            };
+            self.output.status_message.set_message(match (replacements, lines) {
+              (0, _) => format!("Pattern not found: {}", substitution.pattern),
+              (replacements, 1) => format!("{} substitutions on 1 line", replacements),
+              (replacements, lines) => format!("{} substitutions on {} lines", replacements, lines),
+            });
+          },
+          Err(message) => self.output.status_message.set_message(message),
+        }
+      },
+      line if line.strip_prefix(write.as_str()).is_some_and(|rest| rest.starts_with(" !")) => {
+        self.output.pipe_buffer(&line[write.len() + 2..]);
+      },
+      line if line.starts_with("%!") => {
+        self.output.filter_buffer(&line[2..]);
+      },
+      line if line.starts_with("set ") => {
+        for argument in line[4..].split_whitespace() {
+          if let Err(message) = self.output.set_option(argument) {
+            self.output.status_message.set_message(message);
+            break;
+          }
+        }
+      },
+      "e!" => {
+        log::log::log("INFO".to_string(), "Reloading file.".to_string());
+        self.output.reload();
+      },
+      line if line.starts_with("e ") => {
+        let filename = line[2..].trim();
+        log::log::log("INFO".to_string(), format!("Opening file: {}", filename));
+        if filename.is_empty() {
+          self.output.status_message.set_message("No file name.".to_string());
+        } else if self.output.edit_file(filename.into()) {
+          self.recover_swap()?;
+        }
+      },
+      line if line.starts_with("colorscheme ") || line.starts_with("colo ") => {
+        let name = line.split_once(' ').map_or("", |(_, name)| name.trim());
+        match theme::set(name) {
+          Ok(()) => self.output.status_message.set_message(format!("Color scheme: {}", name)),
+          Err(message) => self.output.status_message.set_message(message),
+        }
+      },
+      "sp" | "split" => {
+        self.output.split();
+      },
+      "ls" => {
+        self.output.list_buffers();
+      },
+      "noh" | "nohlsearch" => {
+        self.output.clear_search_highlight();
+      },
+      "stats" => {
+        self.output.show_stats();
+      },
+      "bn" => {
+        self.output.cycle_buffers(1);
+      },
+      "bp" => {
+        self.output.cycle_buffers(-1);
+      },
+      "bd" if self.output.dirty => {
+        let answer = prompt!(&mut self.output, "Unsaved changes. Close anyway? (y/n) {}");
+        if answer.is_some_and(|answer| answer.starts_with(['y', 'Y'])) {
+          self.output.close_buffer();
+        } else {
+          self.output.status_message.set_message(format!("File has unsaved changes. Use {}bd! to close it anyway.", keymap.command));
+        }
+      },
+      "bd" | "bd!" => {
+        log::log::log("INFO".to_string(), "Closing buffer.".to_string());
+        self.output.close_buffer();
+      },
+      line if line.parse::<usize>().is_ok() => {
+        log::log::log("INFO".to_string(), format!("Jumping to line {}.", line));
+        self.output.record_jump();
+        self.output.go_to_line(line.parse().unwrap());
+      },
       "" => {}, // do nothing if no command is entered
       _ => {
         log::log::log("INFO".to_string(), format!("Invalid command: {:?}", command));
@@ -214,13 +430,609 @@ impl Editor {
       }
     }
     self.clear_previous_keys();
-    self.set_command_message();
-    return Ok(true)
+    // Only clear the typed command, keep any message the command left behind
+    if self.output.status_message.message() == Some(&command) {
+      self.set_command_message();
+    }
+    Ok(true)
+  }
+
+  fn replace_pending(&self) -> bool {
+    self.previous_command_keys == [KeyCode::Char('r')]
+  }
+
+  fn register_pending(&self) -> bool {
+    matches!(self.previous_command_keys[..], [KeyCode::Char('q' | '@')])
+  }
+
+  fn mark_pending(&self) -> bool {
+    matches!(self.previous_command_keys[..], [KeyCode::Char('m' | '`' | '\'')])
+  }
+
+  fn find_pending(&self) -> bool {
+    matches!(self.previous_command_keys[..], [KeyCode::Char('f' | 'F' | 't' | 'T')])
+  }
+
+  fn in_command_line(&self) -> bool {
+    self.previous_command_keys.first() == Some(&KeyCode::Char(CONFIG.keymap.command))
+  }
+
+  // Replaces the command being typed with an earlier one, which can be edited before Enter
+  fn recall_command(&mut self, direction: KeyCode) {
+    let history = &mut self.output.command_history;
+    let recalled = match direction {
+      KeyCode::Up => history.older(),
+      _ => history.newer(),
+    };
+    if let Some(recalled) = recalled {
+      self.previous_command_keys = iter::once(CONFIG.keymap.command)
+        .chain(recalled.chars())
+        .map(KeyCode::Char)
+        .collect();
+      self.set_command_message();
+    }
+  }
+
+  fn repeat_motion(&mut self, code: KeyCode, count: Option<usize>) {
+    let direction = match code {
+      KeyCode::Char('h') => KeyCode::Left,
+      KeyCode::Char('j') => KeyCode::Down,
+      KeyCode::Char('k') => KeyCode::Up,
+      KeyCode::Char('l') => KeyCode::Right,
+      KeyCode::Char('0') => KeyCode::Home,
+      KeyCode::Char('$') => KeyCode::End,
+      code => code,
+    };
+    // Stops early at the edge of the file or line, where the rest would do nothing
+    for _ in 0..count.unwrap_or(1) {
+      let cursor = self.output.cursor_controller;
+      self.output.move_cursor(direction);
+      if (cursor.cursor_y, cursor.cursor_x) == (self.output.cursor_controller.cursor_y, self.output.cursor_controller.cursor_x) {
+        break;
+      }
+    }
+  }
+
+  // Starts recording a change with the key that made it, keeping any register prefix
+  fn begin_change(&mut self, code: KeyCode, count: Option<usize>) {
+    let mut keys = self.previous_command_keys.clone();
+    keys.push(code);
+    self.change_keys = Some((keys, count));
+  }
+
+  fn end_change(&mut self) {
+    if let Some(change) = self.change_keys.take() {
+      self.last_change = change;
+    }
+  }
+
+  // Feeds the last change's keys back through, as if they were typed again
+  fn repeat_last_change(&mut self, count: Option<usize>) -> crossterm::Result<bool> {
+    let (keys, change_count) = self.last_change.clone();
+    // A count replaces the one the change was made with, otherwise it repeats the change
+    let (repeats, count) = match change_count {
+      Some(_) => (1, count.or(change_count)),
+      None => (count.unwrap_or(1), None),
+    };
+    for _ in 0..repeats {
+      for (i, &key) in keys.iter().enumerate() {
+        match self.mode {
+          EditorModes::Insert => self.process_insert_key(key),
+          _ => {
+            self.process_command_key(key, if i == keys.len() - 1 { count } else { None })?;
+          },
+        }
+      }
+    }
+    Ok(true)
+  }
+
+  // Feeds a recorded macro back through as if it were typed, `@@` plays the last one again
+  fn replay_macro(&mut self, register: char, count: usize) -> crossterm::Result<bool> {
+    let register = match register {
+      '@' => match self.last_macro {
+        Some(register) => register,
+        None => {
+          self.output.status_message.set_message("No previous macro.".to_string());
+          return Ok(true);
+        },
+      },
+      register => register,
+    };
+    if self.replaying.contains(&register) {
+      self.output.status_message.set_message(format!("Macro @{} can't call itself.", register));
+      return Ok(true);
+    }
+    let Some(keys) = self.macros.get(&register).cloned() else {
+      self.output.status_message.set_message(format!("Nothing recorded in @{}", register));
+      return Ok(true);
+    };
+    log::log::log("INFO".to_string(), format!("Replaying {:?} {} times", register, count));
+    self.last_macro = Some(register);
+    self.replaying.push(register);
+    for _ in 0..count {
+      for &key_event in &keys {
+        if !self.process_key(key_event)? {
+          return Ok(false);
+        }
+      }
+    }
+    self.replaying.pop();
+    Ok(true)
+  }
+
+  fn process_command_key(&mut self, code: KeyCode, count: Option<usize>) -> crossterm::Result<bool> {
+    match code {
+      KeyCode::Char(_) if self.in_command_line() => {
+        self.set_previous_key(code);
+      },
+      KeyCode::Char(replacement) if self.replace_pending() => {
+        log::log::log("INFO".to_string(), format!("Replacing with {:?}", replacement));
+        self.begin_change(code, count);
+        self.end_change();
+        self.clear_previous_keys();
+        self.set_command_message();
+        self.output.replace_characters(replacement, count.unwrap_or(1));
+      },
+      KeyCode::Char(name) if self.mark_pending() => {
+        let Some(&KeyCode::Char(kind)) = self.previous_command_keys.first() else {
+          unreachable!()
+        };
+        self.clear_previous_keys();
+        self.set_command_message();
+        match kind {
+          _ if !name.is_ascii_lowercase() => {
+            self.output.status_message.set_message("E191: Argument must be a letter a-z".to_string());
+          },
+          'm' => self.output.set_mark(name),
+          _ => self.output.jump_to_mark(name, kind == '\''),
+        }
+      },
+      KeyCode::Char('m' | '`' | '\'') => {
+        // The next key typed names the mark
+        self.clear_previous_keys();
+        self.set_previous_key(code);
+      },
+      KeyCode::Char(target) if self.find_pending() => {
+        let Some(&KeyCode::Char(kind)) = self.previous_command_keys.first() else {
+          unreachable!()
+        };
+        self.clear_previous_keys();
+        self.set_command_message();
+        self.last_find = Some((kind, target));
+        self.output.find_in_line(kind, target, count.unwrap_or(1), false);
+      },
+      KeyCode::Char('f' | 'F' | 't' | 'T') => {
+        // The next key typed is the character to find
+        self.clear_previous_keys();
+        self.set_previous_key(code);
+        self.count = count;
+      },
+      KeyCode::Char(repeat @ (';' | ',')) => {
+        self.clear_previous_keys();
+        if let Some((kind, target)) = self.last_find {
+          // `,` goes the other way
+          let kind = match (repeat, kind) {
+            (';', kind) => kind,
+            (_, 'f') => 'F',
+            (_, 'F') => 'f',
+            (_, 't') => 'T',
+            _ => 't',
+          };
+          self.output.find_in_line(kind, target, count.unwrap_or(1), true);
+        }
+      },
+      KeyCode::Char(register) if self.register_pending() => {
+        let start_recording = self.previous_command_keys == [KeyCode::Char('q')];
+        self.clear_previous_keys();
+        self.set_command_message();
+        if start_recording {
+          log::log::log("INFO".to_string(), format!("Recording into {:?}", register));
+          self.recording = Some((register, Vec::new()));
+        } else {
+          return self.replay_macro(register, count.unwrap_or(1));
+        }
+      },
+      KeyCode::Char('q') if self.previous_command_keys == [KeyCode::Char('g')] => {
+        log::log::log("INFO".to_string(), "Formatting paragraph.".to_string());
+        self.begin_change(code, None);
+        self.end_change();
+        self.clear_previous_keys();
+        self.set_command_message();
+        if let Some(paragraph) = self.output.paragraph() {
+          self.output.format_lines(paragraph);
+        }
+      },
+      KeyCode::Char('q') if self.recording.is_some() => {
+        if let Some((register, mut keys)) = self.recording.take() {
+          // The `q` that stopped the recording isn't part of it
+          keys.pop();
+          self.macros.insert(register, keys);
+          self.output.status_message.set_message(format!("Recorded @{}", register));
+        }
+      },
+      KeyCode::Char('q' | '@') => {
+        // The next key typed is the register, and the count carries over to it
+        self.clear_previous_keys();
+        self.set_previous_key(code);
+        self.count = count;
+      },
+      KeyCode::Char('r') => {
+        // The next key typed is the replacement, and the count carries over to it
+        self.clear_previous_keys();
+        self.set_previous_key(code);
+        self.count = count;
+      },
+      KeyCode::Char(key) if key == CONFIG.keymap.command => {
+        log::log::log("DEBUG".to_string(), "Beginning command.".to_string());
+        self.output.command_history.reset();
+        self.clear_previous_keys();
+        self.set_previous_key(code);
+      },
+      KeyCode::Char(key) if key == CONFIG.keymap.insert => {
+        self.begin_change(code, None);
+        self.set_mode(EditorModes::Insert);
+      },
+      KeyCode::Char(append @ ('a' | 'A' | 'I')) => {
+        self.clear_previous_keys();
+        self.begin_change(code, None);
+        match append {
+          'I' => self.output.move_to_first_non_blank(),
+          _ => self.output.move_to_append(append == 'A'),
+        }
+        self.set_mode(EditorModes::Insert);
+      },
+      KeyCode::Char(key) if key == CONFIG.keymap.visual => {
+        self.set_mode(EditorModes::Visual);
+      },
+      KeyCode::Char('w' | 'e' | 'b') if self.previous_command_keys == [KeyCode::Char('d')] => {
+        log::log::log("INFO".to_string(), format!("Deleting to {:?}", code));
+        self.begin_change(code, count);
+        self.end_change();
+        self.clear_previous_keys();
+        self.set_command_message();
+        self.output.delete_word_motion(code, count.unwrap_or(1));
+      },
+      KeyCode::Char('d') if self.previous_command_keys == [code] => {
+        log::log::log("INFO".to_string(), "Deleting lines.".to_string());
+        self.begin_change(code, count);
+        self.end_change();
+        self.clear_previous_keys();
+        self.set_command_message();
+        self.output.delete_lines(count.unwrap_or(1));
+      },
+      KeyCode::Char('d') => {
+        // Waits for the motion, and the count typed before `d` carries over to it
+        self.clear_previous_keys();
+        self.set_previous_key(code);
+        self.count = count;
+      },
+      KeyCode::Char('h' | 'j' | 'k' | 'l' | 'w' | 'b' | 'e' | '0' | '$') => {
+        self.clear_previous_keys();
+        self.repeat_motion(code, count);
+      },
+      KeyCode::Char('^') => {
+        self.clear_previous_keys();
+        self.output.move_to_first_non_blank();
+      },
+      KeyCode::Char('g') => {
+        if self.previous_command_keys == [KeyCode::Char('g')] {
+          log::log::log("INFO".to_string(), "Jumping to first line.".to_string());
+          self.clear_previous_keys();
+          self.set_command_message();
+          self.output.record_jump();
+          self.output.go_to_line(1);
+          self.output.move_to_first_non_blank();
+        } else {
+          self.clear_previous_keys();
+          self.set_previous_key(code);
+        }
+      },
+      KeyCode::Char('Z') => {
+        if self.previous_command_keys == [code] {
+          // Like vim, `ZZ` only writes when there are changes, then quits
+          log::log::log("INFO".to_string(), "Saving file and quitting.".to_string());
+          self.clear_previous_keys();
+          if (!self.output.dirty || self.write_file(false)) && !self.output.warn_hidden_changes() {
+            return Ok(false);
+          }
+        } else {
+          self.clear_previous_keys();
+          self.set_previous_key(code);
+        }
+      },
+      KeyCode::Char(shift @ ('>' | '<')) => {
+        if self.previous_command_keys == [code] {
+          log::log::log("INFO".to_string(), format!("Shifting lines {}", shift));
+          self.begin_change(code, count);
+          self.end_change();
+          self.clear_previous_keys();
+          self.set_command_message();
+          let cursor_y = self.output.cursor_controller.cursor_y;
+          self.output.shift_lines(cursor_y..cursor_y + count.unwrap_or(1), shift == '>');
+        } else {
+          // The count typed before the first key carries over to the second
+          self.clear_previous_keys();
+          self.set_previous_key(code);
+          self.count = count;
+        }
+      },
+      KeyCode::Char(open @ ('o' | 'O')) => {
+        log::log::log("INFO".to_string(), "Opening new line.".to_string());
+        self.begin_change(code, None);
+        self.clear_previous_keys();
+        self.output.open_line(open == 'o');
+        self.set_mode(EditorModes::Insert);
+      },
+      KeyCode::Char('"') => {
+        // Register prefix, only the system clipboard register is supported
+        self.clear_previous_keys();
+        self.set_previous_key(code);
+      },
+      KeyCode::Char('+') if self.previous_command_keys == [KeyCode::Char('"')] => {
+        self.set_previous_key(code);
+      },
+      KeyCode::Char('p') => {
+        let from_clipboard = self.previous_command_keys == [KeyCode::Char('"'), KeyCode::Char('+')];
+        log::log::log("INFO".to_string(), format!("Pasting, from clipboard: {}", from_clipboard));
+        self.begin_change(code, None);
+        self.end_change();
+        self.clear_previous_keys();
+        self.set_command_message();
+        self.output.paste(from_clipboard);
+      },
+      KeyCode::Char('G') => {
+        log::log::log("INFO".to_string(), "Jumping to last line.".to_string());
+        self.clear_previous_keys();
+        self.output.record_jump();
+        self.output.go_to_line(count.unwrap_or(self.output.editor_rows.number_of_rows()));
+        self.output.move_to_first_non_blank();
+      },
+      KeyCode::Char(delete @ ('x' | 'X')) => {
+        log::log::log("INFO".to_string(), "Deleting characters.".to_string());
+        self.begin_change(code, count);
+        self.end_change();
+        self.clear_previous_keys();
+        self.output.delete_characters(count.unwrap_or(1), delete == 'X');
+      },
+      KeyCode::Char('J') => {
+        log::log::log("INFO".to_string(), "Joining lines.".to_string());
+        self.begin_change(code, count);
+        self.end_change();
+        self.clear_previous_keys();
+        // Like vim, a count is the number of lines joined, so `J` and `2J` are the same
+        self.output.join_lines(cmp::max(count.unwrap_or(2), 2) - 1);
+      },
+      KeyCode::Char('.') => {
+        log::log::log("INFO".to_string(), format!("Repeating last change: {:?}", self.last_change.0));
+        self.clear_previous_keys();
+        return self.repeat_last_change(count);
+      },
+      KeyCode::Backspace => {
+        // remove last value in previous_command_keys,
+        // Update status message
+        self.clear_last_command_key();
+      },
+      KeyCode::Enter => {
+        log::log::log("DEBUG".to_string(), "Executing command".to_string());
+        return self.process_command()
+      },
+      _ => {
+        self.clear_previous_keys();
+        self.output.status_message.set_message("Invalid command key.".to_string());
+      },
+    }
+    Ok(true)
+  }
+
+  fn process_visual_key(&mut self, code: KeyCode, count: Option<usize>) {
+    let g_pending = self.previous_command_keys == [KeyCode::Char('g')];
+    self.clear_previous_keys();
+    match code {
+      KeyCode::Char('g') => {
+        self.set_previous_key(code);
+      },
+      KeyCode::Char('q') if g_pending => {
+        log::log::log("INFO".to_string(), "Formatting selection.".to_string());
+        if let Some((start, end)) = self.output.selection() {
+          self.output.format_lines(start.0..end.0 + 1);
+        }
+        self.set_mode(EditorModes::Command);
+      },
+      KeyCode::Char('h' | 'j' | 'k' | 'l' | 'w' | 'b' | 'e' | '0' | '$') => {
+        self.repeat_motion(code, count);
+      },
+      KeyCode::Char('^') => {
+        self.output.move_to_first_non_blank();
+      },
+      KeyCode::Char('d') => {
+        log::log::log("INFO".to_string(), "Deleting selection.".to_string());
+        self.output.delete_selection();
+        self.set_mode(EditorModes::Command);
+      },
+      KeyCode::Char(shift @ ('>' | '<')) => {
+        if let Some((start, end)) = self.output.selection() {
+          self.output.shift_lines(start.0..end.0 + 1, shift == '>');
+        }
+        self.set_mode(EditorModes::Command);
+      },
+      KeyCode::Char('y') => {
+        log::log::log("INFO".to_string(), "Yanking selection.".to_string());
+        self.output.yank_selection();
+        self.set_mode(EditorModes::Command);
+      },
+      KeyCode::Esc => {
+        self.set_mode(EditorModes::Command);
+      },
+      _ => {},
+    }
+  }
+
+  fn process_insert_key(&mut self, code: KeyCode) {
+    if let Some((keys, _)) = self.change_keys.as_mut() {
+      keys.push(code);
+    }
+    // A word followed by a separator may be an abbreviation
+    let expanded = match code {
+      KeyCode::Char(ch) if text::is_separator(ch) => self.output.expand_abbreviation(),
+      KeyCode::Tab | KeyCode::Enter => self.output.expand_abbreviation(),
+      _ => false,
+    };
+    if expanded {
+      return;
+    }
+    match code {
+      KeyCode::Char(ch) if self.output.options.autopair => {
+        self.output.insert_paired(ch);
+      },
+      KeyCode::Char(ch) => {
+        self.output.insert_character(ch);
+      },
+      KeyCode::Tab => {
+        self.output.insert_tab();
+      },
+      KeyCode::Backspace => {
+        if self.output.options.autopair {
+          self.output.delete_closing_pair();
+        }
+        (0..self.output.backspace_width()).for_each(|_| self.output.delete_character());
+      },
+      KeyCode::Delete => {
+        self.output.move_cursor(KeyCode::Right);
+        self.output.delete_character();
+      },
+      KeyCode::Enter => {
+        self.output.insert_newline();
+      },
+      KeyCode::Esc => {
+        self.end_change();
+        self.set_mode(EditorModes::Command);
+      },
+      _ => {
+        log::log::log("INFO".to_string(), format!("Unsupported key: {:?}", code));
+        self.output.status_message.set_message("Unsupported key.".to_string());
+      },
+    }
   }
 
   pub fn process_keypress(&mut self) -> crossterm::Result<bool> {
-    match self.reader.read()? {
+    // Returning on a timeout is a tick, giving `run` a chance to redraw and write the swap file
+    let Some(event) = self.output.input.poll()? else {
+      return Ok(true);
+    };
+    let key_event = match event {
+      Event::Resize(columns, rows) => {
+        log::log::log("INFO".to_string(), format!("Resizing to {}x{}", columns, rows));
+        self.output.resize(columns, rows);
+        return Ok(true);
+      },
+      Event::Mouse(mouse_event) => {
+        match mouse_event.kind {
+          MouseEventKind::Down(_) => self.output.click(mouse_event.column, mouse_event.row),
+          MouseEventKind::ScrollUp => self.output.scroll_lines(-3),
+          MouseEventKind::ScrollDown => self.output.scroll_lines(3),
+          _ => {},
+        }
+        return Ok(true);
+      },
+      Event::Key(key_event) => key_event,
+      _ => return Ok(true),
+    };
+    if let Some((_, keys)) = self.recording.as_mut() {
+      keys.push(key_event);
+    }
+    let running = self.process_key(key_event)?;
+    self.update_indicator();
+    Ok(running)
+  }
+
+  // Shows the count and keys waiting for the rest of a command, and the register being
+  // recorded into, so multi-key commands can be seen building up
+  fn update_indicator(&mut self) {
+    let mut pending = self.count.map(|count| count.to_string()).unwrap_or_default();
+    if !self.in_command_line() {
+      pending.extend(self.previous_command_keys.iter().filter_map(|key| match key {
+        KeyCode::Char(ch) => Some(*ch),
+        _ => None,
+      }));
+    }
+    if self.pane_pending {
+      pending.push_str("^W");
+    }
+    let recording = self.recording.as_ref().map(|(register, _)| format!("recording @{}", register));
+    self.output.status_message.indicator = recording
+      .into_iter()
+      .chain(Some(pending).filter(|pending| !pending.is_empty()))
+      .collect::<Vec<String>>()
+      .join("  ");
+  }
+
+  fn process_key(&mut self, key_event: KeyEvent) -> crossterm::Result<bool> {
+    if self.pane_pending {
+      self.pane_pending = false;
+      match key_event.code {
+        KeyCode::Char('w' | 'j') => self.output.cycle_panes(1),
+        KeyCode::Char('W' | 'k') => self.output.cycle_panes(-1),
+        KeyCode::Char('s') => self.output.split(),
+        KeyCode::Char('c' | 'q') => {
+          self.output.close_pane();
+        },
+        _ => {},
+      }
+      return Ok(true);
+    }
+    match key_event {
+      KeyEvent {
+        code: KeyCode::Char(half_page @ ('d' | 'u')),
+        modifiers: event::KeyModifiers::CONTROL,
+        ..
+      } => {
+        self.count = None;
+        self.output.scroll_half_page(half_page == 'd');
+      },
+      KeyEvent {
+        code: KeyCode::Char('o'),
+        modifiers: event::KeyModifiers::CONTROL,
+        ..
+      } if matches!(self.mode, EditorModes::Command) && !self.in_command_line() => {
+        self.count = None;
+        self.output.jump(true);
+      },
+      // Terminals send Ctrl-I as Tab
+      KeyEvent {
+        code: KeyCode::Char('i'),
+        modifiers: event::KeyModifiers::CONTROL,
+        ..
+      }
+      | KeyEvent {
+        code: KeyCode::Tab,
+        modifiers: event::KeyModifiers::NONE,
+        ..
+      } if matches!(self.mode, EditorModes::Command) && !self.in_command_line() => {
+        self.count = None;
+        self.output.jump(false);
+      },
+      KeyEvent {
+        code: KeyCode::Char('g'),
+        modifiers: event::KeyModifiers::CONTROL,
+        ..
+      } if self.previous_command_keys == [KeyCode::Char('g')] => {
+        self.clear_previous_keys();
+        self.output.show_stats();
+      },
+      KeyEvent {
+        code: KeyCode::Char('w'),
+        modifiers: event::KeyModifiers::CONTROL,
+        ..
+      } if !matches!(self.mode, EditorModes::Insert) => {
+        self.pane_pending = true;
+      },
       /* Cursor Control */
+      KeyEvent {
+        code: direction @ (KeyCode::Up | KeyCode::Down),
+        modifiers: event::KeyModifiers::NONE,
+        ..
+      } if self.in_command_line() => self.recall_command(direction),
       KeyEvent {
         code: direction @ (
           KeyCode::Up 
@@ -233,24 +1045,25 @@ impl Editor {
         modifiers: event::KeyModifiers::NONE,
         ..
       } => {
-        log::log::log("INFO".to_string(), format!("Moving cursor in direction: {:?}", direction));
-        self.output.move_cursor(direction)
+        log::log::log("DEBUG".to_string(), format!("Moving cursor in direction: {:?}", direction));
+        let count = self.count.take();
+        self.repeat_motion(direction, count)
       },
       KeyEvent {
         code: val @ (KeyCode::PageUp | KeyCode::PageDown),
         modifiers: event::KeyModifiers::NONE,
         ..
       } => {
-        log::log::log("INFO".to_string(), format!("Moving cursor in direction: {:?}", val));
+        log::log::log("DEBUG".to_string(), format!("Moving cursor in direction: {:?}", val));
         if matches!(val, KeyCode::PageUp) {
           self.output.cursor_controller.cursor_y = self.output.cursor_controller.row_offset;
         } else {
           self.output.cursor_controller.cursor_y = cmp::min(
-            self.output.window_size.1 + self.output.cursor_controller.row_offset - 1,
-            self.output.editor_rows.number_of_rows(),
+            (self.output.cursor_controller.screen_rows + self.output.cursor_controller.row_offset).saturating_sub(1),
+            self.output.editor_rows.number_of_rows().saturating_sub(1),
           );
         }
-        (0..self.output.window_size.1).for_each(|_| {
+        (0..self.output.cursor_controller.screen_rows).for_each(|_| {
           self.output.move_cursor(if matches!(val, KeyCode::PageUp) {
             KeyCode::Up
           } else {
@@ -272,68 +1085,23 @@ impl Editor {
         modifiers: event::KeyModifiers::NONE | event::KeyModifiers::SHIFT,
         ..
       } => {
-        if matches!(self.mode, EditorModes::Command) {
-          // Commmand mode controls
-          match code {
-            KeyCode::Char(':') => {
-              log::log::log("INFO".to_string(), "Beginning command.".to_string());
-              self.clear_previous_keys();
-              self.set_previous_key(code);
-            },
-            KeyCode::Char('i') => {
-              self.toggle_mode();
-            },
-            KeyCode::Char('f') // Find
-              | KeyCode::Char('w') // Write
-              | KeyCode::Char('q') // Quit
-              | KeyCode::Char('!') // Force
-              | KeyCode::Char('d') // Delete
-              | KeyCode::Char('o') // New line
-              // | KeyCode::Char('u') // Undo
-              => {
-              self.set_previous_key(code);
-            },
-            KeyCode::Backspace => {
-              // remove last value in previous_command_keys,
-              // Update status message
-              self.clear_last_command_key();
-            },
-            KeyCode::Enter => {
-              log::log::log("INFO".to_string(), "Executing command".to_string());
-              return self.process_command()
-            },
-            _ => {
-              self.clear_previous_keys();
-              self.output.status_message.set_message("Invalid command key.".to_string());
-            },
-          }
-        } else {
-          match code {
-            KeyCode::Char(ch) => {
-              self.output.insert_character(ch);
-            },
-            KeyCode::Tab => {
-              self.output.insert_character('\t');
-            },
-            KeyCode::Backspace => {
-              self.output.delete_character();
-            },
-            KeyCode::Delete => {
-              self.output.move_cursor(KeyCode::Right);
-              self.output.delete_character();
-            },
-            KeyCode::Enter => {
-              self.output.insert_newline();
-            },
-            KeyCode::Esc => {
-              self.toggle_mode();
-            },
-            _ => {
-              log::log::log("INFO".to_string(), format!("Unsupported key: {:?}", code));
-              self.output.status_message.set_message("Unsupported key.".to_string());
-            },
+        if !matches!(self.mode, EditorModes::Insert) && !self.in_command_line() && !self.replace_pending() && !self.find_pending() && !self.register_pending() && !self.mark_pending() {
+          if let KeyCode::Char(digit @ '0'..='9') = code {
+            // A leading 0 is a motion rather than the start of a count
+            if digit != '0' || self.count.is_some() {
+              let digit = digit.to_digit(10).unwrap() as usize;
+              let limit = cmp::max(MAX_COUNT, self.output.editor_rows.number_of_rows());
+              self.count = Some(cmp::min(self.count.unwrap_or(0).saturating_mul(10).saturating_add(digit), limit));
+              return Ok(true);
+            }
           }
         }
+        let count = self.count.take();
+        match self.mode {
+          EditorModes::Command => return self.process_command_key(code, count),
+          EditorModes::Visual => self.process_visual_key(code, count),
+          EditorModes::Insert => self.process_insert_key(code),
+        }
       },
       /* End Text Control */
       _ => {},
@@ -344,12 +1112,15 @@ impl Editor {
 
 pub struct EditorContents {
   pub content: String,
+  // Where each frame goes when flushed, stdout unless testing
+  sink: Box<dyn io::Write>,
 }
 
 impl EditorContents {
-  pub fn new() -> Self {
+  pub fn new(sink: Box<dyn io::Write>) -> Self {
     Self {
       content: String::new(),
+      sink,
     }
   }
 
@@ -378,19 +1149,25 @@ impl io::Write for EditorContents {
   }
 
   fn flush(&mut self) -> io::Result<()> {
-    let out = write!(io::stdout(), "{}", self.content);
-    io::stdout().flush()?;
+    let out = write!(self.sink, "{}", self.content);
+    self.sink.flush()?;
     self.content.clear();
     out
   }
 }
 
-#[derive(Default)]
 pub struct Row {
   pub row_content: String,
   pub render: String,
   pub highlight: Vec<HighlightType>,
   pub is_comment: bool,
+  // The row before changed comment state below the screen, so this row's highlight
+  // is out of date until it's about to be drawn
+  pub stale: bool,
+  pub tab_stop: usize,
+  // Edited, or added as a new row, since the file was last read or written
+  pub modified: bool,
+  pub added: bool,
 }
 
 impl Row {
@@ -400,6 +1177,10 @@ impl Row {
       render,
       highlight: Vec::new(),
       is_comment: false,
+      stale: false,
+      tab_stop: CONFIG.spaces_per_tab,
+      modified: false,
+      added: false,
     }
   }
 
@@ -410,7 +1191,7 @@ impl Row {
       if character == ' ' {
         indent += 1;
       } else if character == '\t' {
-        indent += CONFIG.spaces_per_tab;
+        indent += self.tab_stop;
       } else {
         break;
       }
@@ -418,13 +1199,57 @@ impl Row {
     indent
   }
 
+  pub fn indentation(&self) -> &str {
+    // The leading whitespace exactly as written, tabs and all
+    let length = self.row_content.len() - self.row_content.trim_start().len();
+    &self.row_content[..length]
+  }
+
+  pub fn first_non_blank(&self) -> usize {
+    self.row_content
+      .chars()
+      .position(|c| !c.is_whitespace())
+      .unwrap_or_else(|| self.char_count())
+  }
+
+  // Columns `c` takes when it starts at render column `column`, all the tab math
+  // between `row_content` and `render` goes through here
+  pub fn column_width(&self, c: char, column: usize) -> usize {
+    if c == '\t' {
+      text::tab_width(column, self.tab_stop)
+    } else {
+      text::char_width(c)
+    }
+  }
+
+  // Byte offset into `render` for every character of `row_content`
+  pub fn render_indices(&self) -> Vec<usize> {
+    let mut render_x = 0;
+    let mut index = 0;
+    self.row_content
+      .chars()
+      .map(|c| {
+        let start = index;
+        let width = self.column_width(c, render_x);
+        render_x += width;
+        index += if c == '\t' { width } else { c.len_utf8() };
+        start
+      })
+      .collect()
+  }
+
+  // Render columns are screen columns, so a full-width character spans two of them
+  pub fn render_column(&self, cursor_x: usize) -> usize {
+    self.row_content
+      .chars()
+      .take(cursor_x)
+      .fold(0, |render_x, c| render_x + self.column_width(c, render_x))
+  }
+
   pub fn get_row_content_x(&self, render_x: usize) -> usize {
     let mut current_render_x = 0;
     for(cursor_x, character) in self.row_content.chars().enumerate() {
-      if character == '\t' {
-        current_render_x += (CONFIG.spaces_per_tab - 1) - (current_render_x % CONFIG.spaces_per_tab);
-      }
-      current_render_x += 1;
+      current_render_x += self.column_width(character, current_render_x);
       if current_render_x > render_x {
         return cursor_x;
       }
@@ -432,108 +1257,498 @@ impl Row {
     0
   }
 
+  pub fn char_count(&self) -> usize {
+    self.row_content.chars().count()
+  }
+
+  pub fn byte_index(&self, at: usize) -> usize {
+    // Cursor positions count characters, but String operations need byte offsets
+    Self::char_to_byte_index(&self.row_content, at)
+  }
+
+  // Byte offset into `render` of the first character at or after render column `at`
+  pub fn render_byte_index(&self, at: usize) -> usize {
+    let mut column = 0;
+    for (index, c) in self.render.char_indices() {
+      if column >= at {
+        return index;
+      }
+      column += text::char_width(c);
+    }
+    self.render.len()
+  }
+
+  // Where the text at byte offsets `range` of `row_content` ends up in `render`, which
+  // is what `highlight` is indexed by. Tabs and wide characters make them differ
+  pub fn render_byte_range(&self, range: Range<usize>) -> Range<usize> {
+    let to_render = |byte: usize| {
+      let cursor_x = self.row_content[..byte].chars().count();
+      self.render_byte_index(self.render_column(cursor_x))
+    };
+    to_render(range.start)..to_render(range.end)
+  }
+
+  fn char_to_byte_index(string: &str, at: usize) -> usize {
+    string
+      .char_indices()
+      .nth(at)
+      .map(|(index, _)| index)
+      .unwrap_or(string.len())
+  }
+
   pub fn insert_character(&mut self, at: usize, character: char) {
-    self.row_content.insert(at, character);
+    let index = self.byte_index(at);
+    self.row_content.insert(index, character);
     EditorRows::render_row(self)
   }
 
   pub fn delete_character(&mut self, at: usize) {
-    self.row_content.remove(at);
+    let index = self.byte_index(at);
+    self.row_content.remove(index);
     EditorRows::render_row(self)
   }
 }
 
+#[derive(Copy, Clone, PartialEq)]
+pub enum LineEnding {
+  Lf,
+  CrLf,
+}
+
+impl LineEnding {
+  pub fn detect(contents: &str) -> Self {
+    // Go with whichever ending the majority of lines use
+    let crlf = contents.matches("\r\n").count();
+    let lf = contents.matches('\n').count() - crlf;
+    if crlf > lf { LineEnding::CrLf } else { LineEnding::Lf }
+  }
+
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      LineEnding::Lf => "\n",
+      LineEnding::CrLf => "\r\n",
+    }
+  }
+}
+
 pub struct EditorRows {
   pub row_contents: Vec<Row>,
   pub filename: Option<PathBuf>,
   pub file_size: Option<u64>,
+  pub line_ending: LineEnding,
+  modified_time: Option<SystemTime>,
+  pub tab_stop: usize,
+  // Whether the last line ends with a line break, new files get one
+  pub end_of_line: bool,
+  // Follows `Options::number`, kept here since the gutter's width is worked out here
+  pub line_numbers: bool,
+  // Shown while any row has a sign, set by `Output` before drawing
+  pub sign_column: bool,
+  // Set with `m`, as cursor_y, cursor_x. Kept with the rows so they follow lines
+  // inserted or deleted above them and go along when switching buffers
+  pub marks: HashMap<char, (usize, usize)>,
+  // Where big motions left from, oldest first, and how far back Ctrl-O has gone
+  pub jump_list: Vec<(usize, usize)>,
+  pub jump_index: usize,
 }
 
 impl EditorRows {
-  pub fn new(syntax_highlight: &mut Option<Box<dyn SyntaxHighlight>>) -> Self {
+  pub fn new(syntax_highlight: &mut Option<Box<dyn SyntaxHighlight>>) -> io::Result<Self> {
     
-    match env::args().nth(1) {
-      None => Self {
-        row_contents: Vec::new(),
-        filename: None,
-        file_size: None,
+    match Self::file_argument().0 {
+      Some(file) => Self::from_file(file, syntax_highlight),
+      // `-`, or text piped in without a file, is read from stdin
+      None if env::args().skip(1).any(|arg| arg == "-") || !io::stdin().is_terminal() => {
+        Self::from_stdin(syntax_highlight)
       },
-      Some(file) => Self::from_file(file.into(), syntax_highlight),
+      None => Ok(Self::empty()),
+    }
+  }
+
+  // The file named on the command line and the line to start on, from `+N` or a
+  // trailing `:N` like `src/lib.rs:42`. Flags like `-R` are handled elsewhere
+  pub fn file_argument() -> (Option<PathBuf>, Option<usize>) {
+    let arguments: Vec<String> = env::args().skip(1).collect();
+    // A bare `+` starts on the last line
+    let mut line = arguments
+      .iter()
+      .filter_map(|arg| arg.strip_prefix('+'))
+      .find_map(|number| if number.is_empty() { Some(usize::MAX) } else { number.parse().ok() });
+    let Some(file) = arguments.iter().find(|arg| !arg.starts_with(['-', '+'])) else {
+      return (None, line);
+    };
+    // Only digits after the last colon count, so `C:\notes.txt` stays a path, and a
+    // file whose name really ends that way is opened as it is
+    let line_spec = file
+      .rsplit_once(':')
+      .filter(|(path, number)| {
+        !path.is_empty() && !number.is_empty() && number.bytes().all(|b| b.is_ascii_digit())
+      })
+      .filter(|_| !Path::new(file).exists());
+    match line_spec {
+      Some((path, number)) => {
+        line = number.parse().ok().or(line);
+        (Some(PathBuf::from(path)), line)
+      },
+      None => (Some(PathBuf::from(file)), line),
+    }
+  }
+
+  // An unnamed buffer holding stdin, so the first save asks for a name. Keys are
+  // still read from the terminal, crossterm opens /dev/tty when stdin isn't one
+  fn from_stdin(syntax_highlight: &mut Option<Box<dyn SyntaxHighlight>>) -> io::Result<Self> {
+    Self::from_reader(io::stdin(), syntax_highlight).map_err(|err| match err.kind() {
+      io::ErrorKind::InvalidData => io::Error::new(err.kind(), "stdin is not UTF-8 text"),
+      _ => err,
+    })
+  }
+
+  // An unnamed buffer holding `contents`, highlighted with `syntax_highlight` when given
+  pub fn from_str(contents: &str, syntax_highlight: &Option<Box<dyn SyntaxHighlight>>) -> Self {
+    Self {
+      row_contents: Self::rows_from(contents, syntax_highlight, CONFIG.spaces_per_tab),
+      line_ending: LineEnding::detect(contents),
+      end_of_line: Self::ends_with_line_break(contents),
+      ..Self::empty()
+    }
+  }
+
+  // Like `from_str`, for text that isn't in memory yet
+  pub fn from_reader<R: Read>(mut reader: R, syntax_highlight: &Option<Box<dyn SyntaxHighlight>>) -> io::Result<Self> {
+    let mut contents = String::new();
+    reader.read_to_string(&mut contents)?;
+    Ok(Self::from_str(&contents, syntax_highlight))
+  }
+
+  // An unnamed buffer with nothing in it
+  pub fn empty() -> Self {
+    Self {
+      row_contents: Vec::new(),
+      filename: None,
+      file_size: None,
+      line_ending: LineEnding::Lf,
+      modified_time: None,
+      tab_stop: CONFIG.spaces_per_tab,
+      end_of_line: true,
+      line_numbers: true,
+      sign_column: false,
+      marks: HashMap::new(),
+      jump_list: Vec::new(),
+      jump_index: 0,
     }
   }
 
+  // Moves marks and jumps on `at` and below by `by` rows. Ones on removed rows end
+  // up on the row before, which is where joins and deletes leave their text
+  fn shift_positions(&mut self, at: usize, by: isize) {
+    let shift = |y: &mut usize| {
+      if *y >= at {
+        *y = cmp::max(y.saturating_add_signed(by), at.saturating_sub(1));
+      }
+    };
+    self.marks.values_mut().for_each(|(y, _)| shift(y));
+    self.jump_list.iter_mut().for_each(|(y, _)| shift(y));
+  }
+
+  pub fn remove_rows(&mut self, rows: Range<usize>) {
+    self.shift_positions(rows.start, -(rows.len() as isize));
+    self.row_contents.drain(rows);
+  }
+
   pub fn join_adjacent_rows(&mut self, at: usize) {
     let current_row = self.row_contents.remove(at);
+    self.shift_positions(at, -1);
     let previous_row = self.get_editor_row_mut(at - 1);
 
     previous_row.row_content.push_str(&current_row.row_content);
     Self::render_row(previous_row);
   }
 
-  pub fn save(&mut self) -> io::Result<()> {
+  // Adds `indent` to the start of a row that isn't empty, returning how many characters it added
+  pub fn indent_row(&mut self, at: usize, indent: &str) -> isize {
+    let row = self.get_editor_row_mut(at);
+    if row.row_content.is_empty() {
+      return 0;
+    }
+    row.row_content.insert_str(0, indent);
+    Self::render_row(row);
+    indent.chars().count() as isize
+  }
+
+  // Removes a leading tab or up to a tab stop of spaces, returning how many characters went
+  pub fn dedent_row(&mut self, at: usize) -> usize {
+    let row = self.get_editor_row_mut(at);
+    let removed = if row.row_content.starts_with('\t') {
+      1
+    } else {
+      row.row_content.chars().take(row.tab_stop).take_while(|&c| c == ' ').count()
+    };
+    row.row_content.drain(..removed);
+    Self::render_row(row);
+    removed
+  }
+
+  // Joins the next row onto `at` like vim's `J`, returning the column where they meet
+  pub fn join_with_next(&mut self, at: usize) -> Option<usize> {
+    if at + 1 >= self.number_of_rows() {
+      return None;
+    }
+    let next = self.row_contents.remove(at + 1);
+    self.shift_positions(at + 1, -1);
+    let row = self.get_editor_row_mut(at);
+    let join = row.char_count();
+    // The next line's indentation collapses into a single space
+    let next_content = next.row_content.trim_start();
+    if !row.row_content.is_empty() && !next_content.is_empty() && !row.row_content.ends_with(char::is_whitespace) {
+      row.row_content.push(' ');
+    }
+    row.row_content.push_str(next_content);
+    Self::render_row(row);
+    Some(join)
+  }
+
+  // The text as it's written to disk, with a final line break only if the file had one
+  pub fn contents(&self) -> String {
+    let mut contents = self
+      .row_contents
+      .iter()
+      .map(|it| it.row_content.as_str())
+      .collect::<Vec<&str>>()
+      .join(self.line_ending.as_str());
+    if self.end_of_line && !self.row_contents.is_empty() {
+      contents.push_str(self.line_ending.as_str());
+    }
+    contents
+  }
+
+  // An empty file has no last line to end, so saving it with text adds a line break
+  fn ends_with_line_break(contents: &str) -> bool {
+    contents.is_empty() || contents.ends_with('\n')
+  }
+
+  // With `backup`, whatever is on disk is copied to `<filename>~` before being overwritten
+  pub fn save(&mut self, backup: bool) -> io::Result<()> {
     match &self.filename {
-      None => Err(io::Error::new(io::ErrorKind::Other, "No filename specified.")),
+      None => Err(io::Error::other("No filename specified.")),
       Some(name) => {
-        let mut file = fs::OpenOptions::new()
-          .write(true)
-          .create(true)
-          .open(name)?;
-
-        let contents: String = self
-          .row_contents
-          .iter()
-          .map(|it| it.row_content.as_str())
-          .collect::<Vec<&str>>()
-          .join("\n");
-
-        let size = contents.as_bytes().len() as u64;
-        file.set_len(size)?;
-        self.file_size = Some(size);
-        file.write_all(contents.as_bytes())
+        if backup && name.exists() {
+          let mut backup_name = name.clone().into_os_string();
+          backup_name.push("~");
+          fs::copy(name, &backup_name)?;
+          log::log::log("INFO".to_string(), format!("Backed up to {}", Path::new(&backup_name).display()));
+        }
+        let contents = self.contents();
+        Self::write_atomically(name, contents.as_bytes())?;
+        self.file_size = Some(contents.len() as u64);
+        self.modified_time = Self::modified_time(name);
+        self.row_contents.iter_mut().for_each(|row| {
+          row.modified = false;
+          row.added = false;
+        });
+        Ok(())
       }
     }
   }
 
+  // Writes a temporary file beside the target and renames it over the top, so a
+  // crash mid-write never leaves the file half written
+  fn write_atomically(name: &Path, contents: &[u8]) -> io::Result<()> {
+    // Write through symlinks rather than replacing them
+    let target = fs::canonicalize(name).unwrap_or_else(|_| name.to_path_buf());
+    let mut temporary_name = target.clone().into_os_string();
+    temporary_name.push(format!(".vimrs-{}.tmp", std::process::id()));
+    let temporary_name = PathBuf::from(temporary_name);
+
+    let result = (|| {
+      let mut file = fs::File::create(&temporary_name)?;
+      file.write_all(contents)?;
+      if let Ok(metadata) = fs::metadata(&target) {
+        file.set_permissions(metadata.permissions())?;
+      }
+      file.sync_all()?;
+      fs::rename(&temporary_name, &target)
+    })();
+    if result.is_err() {
+      let _ = fs::remove_file(&temporary_name);
+    }
+    result
+  }
+
+  // Line breaks read as whitespace so motions flow across rows
+  pub fn char_at(&self, (y, x): (usize, usize)) -> char {
+    self.get_row(y).chars().nth(x).unwrap_or('\n')
+  }
+
+  pub fn next_position(&self, (y, x): (usize, usize)) -> Option<(usize, usize)> {
+    if x < self.get_editor_row(y).char_count() {
+      Some((y, x + 1))
+    } else if y + 1 < self.number_of_rows() {
+      Some((y + 1, 0))
+    } else {
+      None
+    }
+  }
+
+  pub fn previous_position(&self, (y, x): (usize, usize)) -> Option<(usize, usize)> {
+    if x > 0 {
+      Some((y, x - 1))
+    } else if y > 0 {
+      Some((y - 1, self.get_editor_row(y - 1).char_count()))
+    } else {
+      None
+    }
+  }
+
+  fn clamp_position(&self, (y, x): (usize, usize)) -> (usize, usize) {
+    // A column past the end of a row stands for its line break
+    let last = self.number_of_rows() - 1;
+    if y > last {
+      return (last, self.get_editor_row(last).char_count());
+    }
+    let length = self.get_editor_row(y).char_count();
+    match x.cmp(&length) {
+      cmp::Ordering::Greater if y < last => (y + 1, 0),
+      cmp::Ordering::Greater => (y, length),
+      _ => (y, x),
+    }
+  }
+
+  // Positions are (row, column) in characters, with `end` exclusive
+  pub fn get_text(&self, start: (usize, usize), end: (usize, usize)) -> String {
+    if self.number_of_rows() == 0 {
+      return String::new();
+    }
+    let (start, end) = (self.clamp_position(start), self.clamp_position(end));
+    let first = self.get_editor_row(start.0);
+    if start.0 == end.0 {
+      return first.row_content[first.byte_index(start.1)..first.byte_index(end.1)].to_string();
+    }
+    let last = self.get_editor_row(end.0);
+    let mut text = first.row_content[first.byte_index(start.1)..].to_string();
+    for row in &self.row_contents[start.0 + 1..end.0] {
+      text.push('\n');
+      text.push_str(&row.row_content);
+    }
+    text.push('\n');
+    text.push_str(&last.row_content[..last.byte_index(end.1)]);
+    text
+  }
+
+  pub fn delete_text(&mut self, start: (usize, usize), end: (usize, usize)) {
+    if self.number_of_rows() == 0 {
+      return;
+    }
+    let (start, end) = (self.clamp_position(start), self.clamp_position(end));
+    let last = self.get_editor_row(end.0);
+    let tail = last.row_content[last.byte_index(end.1)..].to_string();
+    self.remove_rows(start.0 + 1..end.0 + 1);
+
+    let row = self.get_editor_row_mut(start.0);
+    let index = row.byte_index(start.1);
+    row.row_content.truncate(index);
+    row.row_content.push_str(&tail);
+    Self::render_row(row);
+  }
+
+  // Returns the position just past the inserted text
+  pub fn insert_text(&mut self, (y, x): (usize, usize), text: &str) -> (usize, usize) {
+    if y == self.number_of_rows() {
+      self.insert_row(y, String::new());
+    }
+    let row = self.get_editor_row_mut(y);
+    let index = row.byte_index(x);
+    let tail = row.row_content.split_off(index);
+    let mut lines = text.split('\n');
+    let first_line = lines.next().unwrap_or_default();
+    row.row_content.push_str(first_line);
+    Self::render_row(row);
+
+    let mut end = (y, x + first_line.chars().count());
+    for line in lines {
+      end = (end.0 + 1, line.chars().count());
+      self.insert_row(end.0, line.to_string());
+    }
+    let row = self.get_editor_row_mut(end.0);
+    row.row_content.push_str(&tail);
+    Self::render_row(row);
+    end
+  }
+
   pub fn get_editor_row_mut(&mut self, at: usize) -> &mut Row {
     &mut self.row_contents[at]
   }
 
   pub fn insert_row(&mut self, at: usize, contents: String) {
     let mut new_row = Row::new(contents, String::new());
+    new_row.tab_stop = self.tab_stop;
+    new_row.added = true;
 
     Self::render_row(&mut new_row);
     self.row_contents.insert(at, new_row);
+    self.shift_positions(at, 1);
   }
 
-  pub fn from_file(file: PathBuf, syntax_highlight: &mut Option<Box<dyn SyntaxHighlight>>) -> Self {
+  // Fails with a message naming the file when it's a directory, isn't UTF-8 text or can't be read
+  pub fn from_file(file: PathBuf, syntax_highlight: &mut Option<Box<dyn SyntaxHighlight>>) -> io::Result<Self> {
+    let error = |kind: io::ErrorKind, message: &str| io::Error::new(kind, format!("\"{}\" {}", file.display(), message));
+    if file.is_dir() {
+      return Err(error(io::ErrorKind::Other, "is a directory"));
+    }
+
     // Create the file if it doesn't exist
     fs::OpenOptions::new()
       .write(true)
       .create(true)
+      .truncate(false)
       .read(true)
       .open(&file)
-      .expect("Unable to create file.");
+      .map_err(|err| error(err.kind(), &err.to_string()))?;
+
+    let file_contents = fs::read_to_string(&file).map_err(|err| match err.kind() {
+      io::ErrorKind::InvalidData => error(err.kind(), "is not a UTF-8 text file"),
+      _ => error(err.kind(), &err.to_string()),
+    })?;
 
-    file.extension()
-      .and_then(|ext| ext.to_str())
-      .map(|ext| Output::select_syntax(ext).map(|syntax| syntax_highlight.insert(syntax)));
+    if let Some(syntax) = Output::select_syntax(&file) {
+      *syntax_highlight = Some(syntax);
+    }
 
-    // Convert file_contents to string
-    let file_contents = fs::read_to_string(&file).expect("Unable to read file.");
+    Ok(Self {
+      file_size: Some(file_contents.len() as u64),
+      modified_time: Self::modified_time(&file),
+      filename: Some(file),
+      ..Self::from_str(&file_contents, syntax_highlight)
+    })
+  }
 
+  fn rows_from(contents: &str, syntax_highlight: &Option<Box<dyn SyntaxHighlight>>, tab_stop: usize) -> Vec<Row> {
     let mut row_contents = Vec::new();
-    file_contents.lines().enumerate().for_each(|(i, line)| {
+    contents.lines().enumerate().for_each(|(i, line)| {
       let mut row = Row::new(line.into(), String::new());
-      Self::render_row(&mut row);
+      row.tab_stop = tab_stop;
+      Self::render(&mut row);
       row_contents.push(row);
       if let Some(it) = syntax_highlight {
-        it.update_syntax(i, &mut row_contents)
+        it.highlight_row(i, &mut row_contents);
       }
     });
-    Self {
-      filename: Some(file),
-      row_contents,
-      file_size: Some(file_contents.len() as u64),
+    row_contents
+  }
+
+  // Swaps in new text for every row, keeping the file name
+  pub fn replace_contents(&mut self, contents: &str, syntax_highlight: &Option<Box<dyn SyntaxHighlight>>) {
+    self.row_contents = Self::rows_from(contents, syntax_highlight, self.tab_stop);
+  }
+
+  fn modified_time(file: &Path) -> Option<SystemTime> {
+    fs::metadata(file).and_then(|metadata| metadata.modified()).ok()
+  }
+
+  // Whether something else wrote to the file since it was read or last saved
+  pub fn changed_on_disk(&self) -> bool {
+    match (&self.filename, self.modified_time) {
+      (Some(file), Some(read_at)) => Self::modified_time(file).is_some_and(|modified| modified > read_at),
+      _ => false,
     }
   }
 
@@ -541,6 +1756,18 @@ impl EditorRows {
     self.row_contents.len()
   }
 
+  // Room for the sign column, then the largest line number plus a separating space
+  pub fn gutter_width(&self) -> usize {
+    self.sign_column as usize + self.number_width()
+  }
+
+  pub fn number_width(&self) -> usize {
+    if !self.line_numbers {
+      return 0;
+    }
+    self.number_of_rows().max(1).to_string().len() + 1
+  }
+
   pub fn get_render(&self, at: usize) -> &String {
     &self.row_contents[at].render
   }
@@ -553,31 +1780,41 @@ impl EditorRows {
     &self.row_contents[at]
   }
 
+  // Tabs render wider or narrower, so every row has to be rendered again
+  pub fn set_tab_stop(&mut self, tab_stop: usize) {
+    self.tab_stop = tab_stop;
+    for row in self.row_contents.iter_mut() {
+      row.tab_stop = tab_stop;
+      Self::render(row);
+    }
+  }
+
+  // Every edit to a row's text comes through here
   pub fn render_row(row: &mut Row) {
-    let mut index = 0;
-    let capacity = row
-      .row_content
-      .chars()
-      .fold(0, |acc, next| acc + if next == '\t' { CONFIG.spaces_per_tab } else { 1 });
-    row.render = String::with_capacity(capacity);
-    row.row_content.chars().for_each(|c| {
-      index += 1;
+    row.modified = true;
+    Self::render(row);
+  }
+
+  fn render(row: &mut Row) {
+    let mut column = 0;
+    let mut render = String::with_capacity(row.row_content.len());
+    for c in row.row_content.chars() {
+      let width = row.column_width(c, column);
       if c == '\t' {
-        row.render.push(' ');
-        while index % CONFIG.spaces_per_tab != 0 {
-          row.render.push(' ');
-          index += 1
-        }
+        (0..width).for_each(|_| render.push(' '));
       } else {
-        row.render.push(c)
+        render.push(c);
       }
-    })
+      column += width;
+    }
+    row.render = render;
   }
 }
 
 pub struct StatusMessage {
   pub message: Option<String>,
   pub set_time: Option<Instant>,
+  pub indicator: String, // Right-aligned and kept until replaced, unlike the message
 }
 
 impl StatusMessage {
@@ -585,6 +1822,7 @@ impl StatusMessage {
     Self {
       message: Some(initial_message),
       set_time: Some(Instant::now()),
+      indicator: String::new(),
     }
   }
 
@@ -606,3 +1844,207 @@ impl StatusMessage {
       })
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::sync::LazyLock;
+  use crate::ScriptedInput;
+
+  fn row(content: &str) -> Row {
+    let mut row = Row::new(content.to_string(), String::new());
+    EditorRows::render_row(&mut row);
+    row
+  }
+
+  fn output() -> Output {
+    Output::with_io(Box::new(ScriptedInput::new(Vec::new())), Box::new(io::sink()), 80, 24)
+  }
+
+  // Runs an editor on `keys` as if they were typed, with Esc as `\x1b` and Enter as `\r`,
+  // until the script runs out, then draws once more so the cursor's columns are current
+  fn type_keys(keys: &str) -> Editor {
+    LazyLock::force(&CONFIG);
+    log::log::set_threshold(log::log::Level::Off);
+    let keys = keys
+      .chars()
+      .map(|c| match c {
+        '\x1b' => KeyCode::Esc,
+        '\r' => KeyCode::Enter,
+        c => KeyCode::Char(c),
+      })
+      .map(|code| KeyEvent::new(code, event::KeyModifiers::NONE))
+      .collect();
+    let output = Output::with_io(Box::new(ScriptedInput::new(keys)), Box::new(io::sink()), 80, 24);
+    let mut editor = Editor::with_output(output);
+    loop {
+      match editor.run() {
+        Ok(true) => {},
+        Ok(false) => break,
+        Err(error) => {
+          assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof, "{}", error);
+          break;
+        },
+      }
+    }
+    editor.output.refresh_screen().unwrap();
+    editor
+  }
+
+  // é takes 2 bytes, 中 3 and 🦀 4, and the last two are full width
+  #[test]
+  fn inserts_around_multibyte_characters() {
+    let mut row = row("é中🦀");
+    row.insert_character(0, 'a');
+    row.insert_character(2, 'b');
+    row.insert_character(4, 'c');
+    row.insert_character(6, 'd');
+    assert_eq!(row.row_content, "aéb中c🦀d");
+    assert_eq!(row.render, row.row_content);
+    assert_eq!(row.char_count(), 7);
+  }
+
+  #[test]
+  fn deletes_multibyte_characters() {
+    let mut row = row("aé中🦀b");
+    row.delete_character(3);
+    assert_eq!(row.row_content, "aé中b");
+    row.delete_character(2);
+    assert_eq!(row.row_content, "aéb");
+    row.delete_character(1);
+    assert_eq!(row.row_content, "ab");
+    assert_eq!(row.render, "ab");
+  }
+
+  #[test]
+  fn cursor_columns_follow_character_widths() {
+    let row = row("é中🦀x");
+    let columns: Vec<usize> = (0..=4).map(|cursor_x| row.render_column(cursor_x)).collect();
+    assert_eq!(columns, [0, 1, 3, 5, 6]);
+    // Either column of a full-width character puts the cursor on it
+    assert_eq!(row.get_row_content_x(1), 1);
+    assert_eq!(row.get_row_content_x(2), 1);
+    assert_eq!(row.get_row_content_x(4), 2);
+    assert_eq!(row.byte_index(3), "é中🦀".len());
+  }
+
+  #[test]
+  fn typing_and_backspacing_move_the_cursor_by_characters() {
+    let mut output = output();
+    "é中🦀".chars().for_each(|c| output.insert_character(c));
+    assert_eq!(output.editor_rows.get_row(0), "é中🦀");
+    assert_eq!(output.cursor_controller.cursor_x, 3);
+    output.delete_character();
+    assert_eq!(output.editor_rows.get_row(0), "é中");
+    assert_eq!(output.editor_rows.get_render(0), "é中");
+    assert_eq!(output.cursor_controller.cursor_x, 2);
+    output.cursor_controller.cursor_x = 1;
+    output.delete_character();
+    assert_eq!(output.editor_rows.get_row(0), "中");
+    assert_eq!(output.cursor_controller.cursor_x, 0);
+  }
+
+  #[test]
+  fn cursor_lands_after_the_character_just_typed() {
+    let editor = type_keys(&format!("{}a中🦀", CONFIG.keymap.insert));
+    assert_eq!(editor.output.editor_rows.get_row(0), "a中🦀");
+    assert_eq!(editor.output.cursor_controller.cursor_x, 3);
+    assert_eq!(editor.output.cursor_controller.render_x, 5);
+  }
+
+  // Each row's render and the render column of every cursor position, at each tab stop
+  #[test]
+  fn mixed_tabs_and_spaces_at_each_tab_stop() {
+    let cases: [(&str, usize, &str, &[usize]); 9] = [
+      (" \tb", 1, "  b", &[0, 1, 2, 3]),
+      (" \tb", 4, "    b", &[0, 1, 4, 5]),
+      (" \tb", 8, "        b", &[0, 1, 8, 9]),
+      ("ab\t\tc", 1, "ab  c", &[0, 1, 2, 3, 4, 5]),
+      ("ab\t\tc", 4, "ab      c", &[0, 1, 2, 4, 8, 9]),
+      ("ab\t\tc", 8, "ab              c", &[0, 1, 2, 8, 16, 17]),
+      ("\t \tx", 1, "   x", &[0, 1, 2, 3, 4]),
+      ("\t \tx", 4, "        x", &[0, 4, 5, 8, 9]),
+      ("\t \tx", 8, "                x", &[0, 8, 9, 16, 17]),
+    ];
+    for (content, tab_stop, render, columns) in cases {
+      let mut row = Row::new(content.to_string(), String::new());
+      row.tab_stop = tab_stop;
+      EditorRows::render_row(&mut row);
+      assert_eq!(row.render, render, "{:?} at tab stop {}", content, tab_stop);
+      let render_x: Vec<usize> = (0..=row.char_count()).map(|cursor_x| row.render_column(cursor_x)).collect();
+      assert_eq!(render_x, columns, "{:?} at tab stop {}", content, tab_stop);
+      // Any column a character covers maps back to that character
+      for (cursor_x, window) in columns.windows(2).enumerate() {
+        for column in window[0]..window[1] {
+          assert_eq!(row.get_row_content_x(column), cursor_x, "{:?} column {} at tab stop {}", content, column, tab_stop);
+        }
+      }
+    }
+  }
+
+  fn rows(editor: &Editor) -> Vec<&str> {
+    let editor_rows = &editor.output.editor_rows;
+    (0..editor_rows.number_of_rows()).map(|at| editor_rows.get_row(at)).collect()
+  }
+
+  #[test]
+  fn scripted_keys_edit_the_buffer() {
+    let editor = type_keys(&format!("{}one\rtwo\rthree\x1bggxjx", CONFIG.keymap.insert));
+    assert_eq!(rows(&editor), ["ne", "wo", "three"]);
+    assert_eq!((editor.output.cursor_controller.cursor_y, editor.output.cursor_controller.cursor_x), (1, 0));
+    assert!(editor.output.dirty);
+  }
+
+  #[test]
+  fn scripted_dot_repeats_the_last_change() {
+    let editor = type_keys(&format!("{}a b c\x1b0x..", CONFIG.keymap.insert));
+    assert_eq!(rows(&editor), [" c"]);
+  }
+
+  #[test]
+  fn marks_follow_rows_inserted_and_joined_above_them() {
+    // `ma` on "three", then a row opened above everything and the first two joined
+    let editor = type_keys(&format!("{}one\rtwo\rthree\x1bmaggOzero\x1bJ", CONFIG.keymap.insert));
+    assert_eq!(rows(&editor), ["zero one", "two", "three"]);
+    assert_eq!(editor.output.editor_rows.marks.get(&'a').map(|&(y, _)| y), Some(2));
+  }
+
+  #[test]
+  fn marks_on_removed_rows_land_on_the_row_before() {
+    let mut editor_rows = EditorRows::from_str("one\ntwo\nthree\nfour", &None);
+    editor_rows.marks.insert('a', (2, 1));
+    editor_rows.marks.insert('b', (3, 0));
+    editor_rows.remove_rows(1..3);
+    assert_eq!(editor_rows.marks[&'a'], (0, 1));
+    assert_eq!(editor_rows.marks[&'b'], (1, 0));
+  }
+
+  #[test]
+  fn maps_a_match_with_a_tab_onto_the_render() {
+    // The tab after "a" runs to column 8, so "a\tb" takes 9 render bytes
+    let mut row = row("xa\tb中c");
+    row.tab_stop = 8;
+    EditorRows::render_row(&mut row);
+    assert_eq!(row.render, format!("xa{}b中c", " ".repeat(6)));
+    assert_eq!(row.render_byte_range(1..4), 1..9);
+    assert_eq!(row.render_byte_range(4..7), 9..12);
+  }
+
+  #[test]
+  fn scripted_dd_deletes_lines_and_dot_repeats_it() {
+    let editor = type_keys(&format!("{}one\rtwo\rthree\rfour\rfive\x1bggjdd.", CONFIG.keymap.insert));
+    assert_eq!(rows(&editor), ["one", "four", "five"]);
+    assert_eq!(editor.output.cursor_controller.cursor_y, 1);
+    assert_eq!(editor.output.register, "three\n");
+
+    let editor = type_keys(&format!("{}one\rtwo\rthree\rfour\rfive\x1bgg2dd.", CONFIG.keymap.insert));
+    assert_eq!(rows(&editor), ["five"]);
+  }
+
+  #[test]
+  fn scripted_colon_d_removes_the_first_row() {
+    let editor = type_keys(&format!("{}one\rtwo\rthree\x1bgg{}d\r", CONFIG.keymap.insert, CONFIG.keymap.command));
+    assert_eq!(rows(&editor), ["two", "three"]);
+    assert_eq!(editor.output.cursor_controller.cursor_y, 0);
+  }
+}
@@ -1,7 +1,8 @@
+use std::cmp;
 use crossterm::{queue, style};
 // use colored::{Colorize, Color};
 
-use super::editor::{Row, EditorContents};
+use super::{editor::{Row, EditorContents}, text};
 
 #[derive(Copy, Clone, Debug)]
 pub enum HighlightType {
@@ -12,9 +13,16 @@ pub enum HighlightType {
   SingleQuoteString,
   Comment,
   MultilineComment,
+  MatchingBracket,
+  UnmatchedBracket,
+  ObjectKey,
+  Heading,
+  Emphasis,
+  CodeSpan,
   Other (style::Color),
 }
 
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub enum FormatType {
   Normal,
   Strikethrough,
@@ -23,37 +31,179 @@ pub enum FormatType {
   Bold,
 }
 
+impl FormatType {
+  pub fn attribute(&self) -> Option<style::Attribute> {
+    match self {
+      FormatType::Normal => None,
+      FormatType::Strikethrough => Some(style::Attribute::CrossedOut),
+      FormatType::Underline => Some(style::Attribute::Underlined),
+      FormatType::Italic => Some(style::Attribute::Italic),
+      FormatType::Bold => Some(style::Attribute::Bold),
+    }
+  }
+
+  // Turns off only this format, so attributes like a reversed selection survive
+  pub fn reset_attribute(&self) -> Option<style::Attribute> {
+    match self {
+      FormatType::Normal => None,
+      FormatType::Strikethrough => Some(style::Attribute::NotCrossedOut),
+      FormatType::Underline => Some(style::Attribute::NoUnderline),
+      FormatType::Italic => Some(style::Attribute::NoItalic),
+      FormatType::Bold => Some(style::Attribute::NormalIntensity),
+    }
+  }
+}
+
 pub trait SyntaxHighlight {
   fn extensions(&self) -> &[&str];
+  // Whole file names, for files like `Makefile` that have no extension to go by
+  fn filenames(&self) -> &[&str] {
+    &[]
+  }
   fn file_type(&self) -> &str;
   fn comment_start(&self) -> &str;
   fn multiline_comment(&self) -> Option<(&str, &str)>;
-  fn update_syntax(&self, at: usize, editor_rows: &mut Vec<Row>);
+  // Highlights the one row, returning whether it now ends in a different multiline
+  // comment state, which means the row after it needs highlighting again
+  fn highlight_row(&self, at: usize, editor_rows: &mut [Row]) -> bool;
+  // Re-highlights a row after an edit, moving down only while the comment state keeps
+  // changing. Rows from `until` on, past the screen, are left alone: the first one the
+  // change reaches is marked stale for `highlight_stale` to pick up once it's drawn
+  fn update_syntax(&self, at: usize, editor_rows: &mut [Row], until: usize) {
+    let mut at = at;
+    while at < editor_rows.len() {
+      editor_rows[at].stale = false;
+      if !self.highlight_row(at, editor_rows) {
+        return;
+      }
+      at += 1;
+      if at >= until && at < editor_rows.len() {
+        editor_rows[at].stale = true;
+        return;
+      }
+    }
+  }
+  // Catches up on the stale rows before `until`, the end of what's about to be drawn
+  fn highlight_stale(&self, editor_rows: &mut [Row], until: usize) {
+    let until = cmp::min(until, editor_rows.len());
+    while let Some(at) = editor_rows[..until].iter().position(|row| row.stale) {
+      self.update_syntax(at, editor_rows, until);
+    }
+  }
+  // Highlights every row once, top to bottom
+  fn update_all_syntax(&self, editor_rows: &mut [Row]) {
+    (0..editor_rows.len()).for_each(|at| {
+      editor_rows[at].stale = false;
+      self.highlight_row(at, editor_rows);
+    });
+  }
   fn syntax_color(&self, highlight_type: &HighlightType) -> style::Color;
+  fn syntax_format(&self, highlight_type: &HighlightType) -> FormatType;
   fn color_row(&self, render: &str, highlight: &[HighlightType], out: &mut EditorContents) {
     let mut current_color = self.syntax_color(&HighlightType::Normal);
+    let mut current_format = FormatType::Normal;
     render.char_indices().for_each(|(i, c)| {
       let color = self.syntax_color(&highlight[i]);
       if current_color != color {
         current_color = color;
         let _ = queue!(out, style::SetForegroundColor(color));
       }
+      let format = self.syntax_format(&highlight[i]);
+      if current_format != format {
+        if let Some(attribute) = current_format.reset_attribute() {
+          let _ = queue!(out, style::SetAttribute(attribute));
+        }
+        if let Some(attribute) = format.attribute() {
+          let _ = queue!(out, style::SetAttribute(attribute));
+        }
+        current_format = format;
+      }
       out.push(c);
     });
+    if let Some(attribute) = current_format.reset_attribute() {
+      let _ = queue!(out, style::SetAttribute(attribute));
+    }
     let _ = queue!(out, style::SetForegroundColor(style::Color::White));
   }
   fn is_separator(&self, c: char) -> bool {
-    c.is_whitespace() || [
-      ',', '.', '(', ')', '+', '-', '/', '*', '=', '~', '%', '<', '>', '"', '\'', ';', '&',
-    ].contains(&c)
+    text::is_separator(c)
+  }
+}
+
+// For config formats: `[section]` lines become headings and the name before
+// `separator` at the start of a line becomes a key
+pub fn highlight_line_keys(render: &str, highlight: &mut [HighlightType], separator: char) {
+  let start = render.len() - render.trim_start().len();
+  if render[start..].starts_with('[') {
+    let end = render[start..].find(']').map_or(render.len(), |end| start + end + 1);
+    highlight[start..end].iter_mut().for_each(|highlight| *highlight = HighlightType::Heading);
+    return;
+  }
+  let Some(end) = render.find(separator) else {
+    return;
+  };
+  let key = render[start..end].trim_end();
+  let is_key = !key.is_empty() && key
+    .chars()
+    .all(|c| c.is_alphanumeric() || ['_', '-', '.', '"', '\'', ' '].contains(&c));
+  if is_key {
+    highlight[start..start + key.len()]
+      .iter_mut()
+      .for_each(|highlight| *highlight = HighlightType::ObjectKey);
   }
 }
 
+// Type suffixes on numbers, Rust's and C's
+const NUMBER_SUFFIXES: [&str; 20] = [
+  "u8", "u16", "u32", "u64", "u128", "usize",
+  "i8", "i16", "i32", "i64", "i128", "isize",
+  "f32", "f64",
+  "ull", "ll", "ul", "u", "l", "f",
+];
+
+// Length of the numeric literal at the start of `text`, like `0xFF`, `1_000`, `1e10` or
+// `3.14f64`. Anything running straight on into a name, like `2abc`, isn't a number
+pub fn number_length(text: &[u8]) -> Option<usize> {
+  let digits = |from: usize, is_digit: fn(&u8) -> bool| {
+    from + text[from..].iter().take_while(|&c| is_digit(c) || *c == b'_').count()
+  };
+  let prefix = text.get(..2).map(|prefix| prefix.to_ascii_lowercase());
+  let mut end = match prefix.as_deref() {
+    Some(b"0x") => digits(2, u8::is_ascii_hexdigit),
+    Some(b"0o") => digits(2, |c| (b'0'..=b'7').contains(c)),
+    Some(b"0b") => digits(2, |c| *c == b'0' || *c == b'1'),
+    _ => {
+      let mut end = digits(0, u8::is_ascii_digit);
+      if text.get(end) == Some(&b'.') && text.get(end + 1).is_some_and(u8::is_ascii_digit) {
+        end = digits(end + 1, u8::is_ascii_digit);
+      }
+      if matches!(text.get(end), Some(b'e' | b'E')) {
+        let sign = usize::from(matches!(text.get(end + 1), Some(b'+' | b'-')));
+        if text.get(end + 1 + sign).is_some_and(u8::is_ascii_digit) {
+          end = digits(end + 1 + sign, u8::is_ascii_digit);
+        }
+      }
+      end
+    },
+  };
+  if let Some(suffix) = NUMBER_SUFFIXES
+    .iter()
+    .find(|suffix| text.len() >= end + suffix.len() && text[end..end + suffix.len()].eq_ignore_ascii_case(suffix.as_bytes())) {
+    end += suffix.len();
+  }
+  let is_word = |c: &u8| c.is_ascii_alphanumeric() || *c == b'_';
+  if text.get(end).is_some_and(is_word) {
+    return None;
+  }
+  Some(end)
+}
+
 #[macro_export]
 macro_rules! syntax_struct {
   (
     struct $Name:ident {
       extensions: $ext:expr,
+      $(filenames: $names:expr,)?
       file_type: $type:expr,
       comment_start: $start:expr,
       keywords: {
@@ -63,10 +213,16 @@ macro_rules! syntax_struct {
       colors: {
         $($highlight:pat => $style_color:expr),*
       }
+      $(, formats: {
+        $($format_highlight:pat => $format:expr),*
+      })?
+      $(, line_keys: $key_separator:expr)?
+      $(, separators: $separators:expr)?
     }
   ) => {
     pub struct $Name {
       extensions: &'static [&'static str],
+      filenames: &'static [&'static str],
       file_type: &'static str,
       comment_start: &'static str,
       multiline_comment: Option<(&'static str, &'static str)>,
@@ -74,8 +230,12 @@ macro_rules! syntax_struct {
 
     impl $Name {
       pub fn new() -> Self {
+        #[allow(unused_variables)]
+        let filenames: &'static [&'static str] = &[];
+        $(let filenames: &'static [&'static str] = &$names;)?
         Self {
           extensions: &$ext,
+          filenames,
           file_type: $type,
           comment_start: $start,
           multiline_comment: $ml_comment,
@@ -88,6 +248,10 @@ macro_rules! syntax_struct {
         self.extensions
       }
 
+      fn filenames(&self) -> &[&str] {
+        self.filenames
+      }
+
       fn file_type(&self) -> &str {
         self.file_type
       }
@@ -101,6 +265,10 @@ macro_rules! syntax_struct {
       }
 
       fn syntax_color(&self, highlight_type: &HighlightType) -> style::Color {
+        // A theme's colors win over the ones given here
+        if let Some(color) = $crate::editor::theme::color(highlight_type) {
+          return color;
+        }
         // Compare highlight_type with the color of the highlight stored in the struct
         // If they match, return the color
         // Otherwise, return the default color
@@ -109,10 +277,37 @@ macro_rules! syntax_struct {
             $highlight => $style_color,
           )*
           HighlightType::Other(color) => *color,
+          // Keys look like any other string unless the language gives them a color
+          #[allow(unreachable_patterns)]
+          HighlightType::ObjectKey => self.syntax_color(&HighlightType::DoubleQuoteString),
+          // Only produced by highlighters that aren't built with this macro
+          #[allow(unreachable_patterns)]
+          _ => self.syntax_color(&HighlightType::Normal),
         }
       }
 
-      fn update_syntax(&self, at: usize, editor_rows: &mut Vec<Row>) {
+      fn syntax_format(&self, highlight_type: &HighlightType) -> FormatType {
+        #[allow(unreachable_patterns)]
+        match highlight_type {
+          $($(
+            $format_highlight => $format,
+          )*)?
+          _ => FormatType::Normal,
+        }
+      }
+
+      $(
+        // Characters that end words for this language on top of the usual ones
+        fn is_separator(&self, c: char) -> bool {
+          $crate::editor::text::is_separator(c) || $separators.contains(&c)
+        }
+      )?
+
+      fn highlight_row(&self, at: usize, editor_rows: &mut [Row]) -> bool {
+        // Callers refresh the row after an edit without checking it exists
+        if at >= editor_rows.len() {
+          return false;
+        }
         let mut in_comment = at > 0 && editor_rows[at - 1].is_comment;
         let current_row = &mut editor_rows[at];
 
@@ -128,15 +323,11 @@ macro_rules! syntax_struct {
         let mut i = 0;
         let mut previous_separater = true;
         let mut in_string: Option<char> = None;
+        let mut string_start = 0;
         let comment_start = self.comment_start().as_bytes();
 
         while i < render.len() {
           let c = render[i] as char;
-          let previous_highlight = if i > 0 {
-            current_row.highlight[i - 1]
-          } else {
-            HighlightType::Normal
-          };
 
           if in_string.is_none() && !comment_start.is_empty() && !in_comment {
             let end = i + comment_start.len();
@@ -177,6 +368,7 @@ macro_rules! syntax_struct {
             add! {
               if val == '"' { HighlightType::DoubleQuoteString } else { HighlightType::SingleQuoteString }
             }
+            // An escape at the end of the line has nothing to escape
             if c == '\\' && i + 1 < render.len() {
               add! {
                 if val == '"' { HighlightType::DoubleQuoteString } else { HighlightType::SingleQuoteString }
@@ -186,13 +378,21 @@ macro_rules! syntax_struct {
             }
             if val == c {
               in_string = None;
+              // A double-quoted string followed by a colon is an object key
+              if c == '"' && render[i + 1..].iter().find(|b| !b.is_ascii_whitespace()) == Some(&b':') {
+                current_row.highlight[string_start..=i]
+                  .iter_mut()
+                  .for_each(|highlight| *highlight = HighlightType::ObjectKey);
+              }
             }
             i += 1;
             previous_separater = true;
             continue;
             // We are in a string if the current character is a quote, there is another quote somewhere in the line, and the previous character is a separator
+          // A quote that ends the line has nothing after it to close it
           } else if (c == '"' || c == '\'') && render[i + 1..].contains(&(c as u8)) && previous_separater {
             in_string = Some(c);
+            string_start = i;
             add! {
               if c == '"' { HighlightType::DoubleQuoteString } else { HighlightType::SingleQuoteString }
             }
@@ -200,18 +400,19 @@ macro_rules! syntax_struct {
             continue;
           }
 
-          if (c.is_digit(10)
-            && (previous_separater 
-              || matches!(previous_highlight, HighlightType::Number)))
-            || (c == '.' && matches!(previous_highlight, HighlightType::Number)) {
-            add!(HighlightType::Number);
-            i += 1;
-            previous_separater = false;
-            continue;
+          if c.is_ascii_digit() && previous_separater {
+            if let Some(length) = number_length(&render[i..]) {
+              (0..length).for_each(|_| add!(HighlightType::Number));
+              i += length;
+              previous_separater = false;
+              continue;
+            }
           }
           if previous_separater {
             $(
               $(
+                // A keyword running past the end of the line is never followed by a separator
+                // or the end, so it's rejected before `render` is sliced
                 let end = i + $words.len();
                 let is_end_or_sep = render
                   .get(end)
@@ -231,12 +432,110 @@ macro_rules! syntax_struct {
           i += 1;
         }
         assert_eq!(current_row.render.len(), current_row.highlight.len());
+        $(
+          highlight_line_keys(&current_row.render, &mut current_row.highlight, $key_separator);
+        )?
         let changed = current_row.is_comment != in_comment;
         current_row.is_comment = in_comment;
-        if (changed && at + 1 < editor_rows.len()) {
-          self.update_syntax(at + 1, editor_rows)
-        }
+        changed
       }
     }
   };
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::editor::editor::EditorRows;
+  use crate::editor::syntax::{JsonHighlight, RustHighlight};
+
+  // One character per byte of the row: `.` plain, `"` and `'` strings, `k` keywords,
+  // `0` numbers and `K` object keys
+  fn highlight_with(syntax: &dyn SyntaxHighlight, render: &str) -> String {
+    let mut row = Row::new(render.to_string(), String::new());
+    EditorRows::render_row(&mut row);
+    let mut rows = vec![row];
+    syntax.highlight_row(0, &mut rows);
+    assert_eq!(rows[0].highlight.len(), render.len());
+    rows[0].highlight
+      .iter()
+      .map(|highlight| match highlight {
+        HighlightType::Normal => '.',
+        HighlightType::DoubleQuoteString => '"',
+        HighlightType::SingleQuoteString => '\'',
+        HighlightType::Other(_) => 'k',
+        HighlightType::Number => '0',
+        HighlightType::ObjectKey => 'K',
+        _ => '?',
+      })
+      .collect()
+  }
+
+  fn highlight(render: &str) -> String {
+    highlight_with(&RustHighlight::new(), render)
+  }
+
+  #[test]
+  fn lone_quote_at_end_of_line_starts_no_string() {
+    assert_eq!(highlight("\""), ".");
+    assert_eq!(highlight("x = '"), ".....");
+  }
+
+  #[test]
+  fn lone_backslash_at_end_of_line() {
+    assert_eq!(highlight("\\"), ".");
+    // The backslash escapes the closing quote, so the string runs to the end
+    assert_eq!(highlight("\"a\\\""), "\"\"\"\"");
+  }
+
+  #[test]
+  fn keyword_at_end_of_line() {
+    assert_eq!(highlight("match"), "kkkkk");
+    assert_eq!(highlight("x match"), "..kkkkk");
+    // Cut short by the end of the line, where reading the whole keyword would slice past it
+    assert_eq!(highlight("x matc"), "......");
+  }
+
+  #[test]
+  fn json_brackets_and_colons_separate_values() {
+    assert_eq!(highlight_with(&JsonHighlight::new(), "{\"a\":true,\"b\":[1,null]}"), ".KKK.kkkk.KKK..0.kkkk..");
+  }
+
+  #[test]
+  fn brackets_and_colons_stay_part_of_words_elsewhere() {
+    assert_eq!(highlight("x:match"), ".......");
+    assert_eq!(highlight("[match]"), ".......");
+  }
+
+  fn rust_rows(text: &str) -> Vec<Row> {
+    let mut rows = EditorRows::from_str(text, &None).row_contents;
+    RustHighlight::new().update_all_syntax(&mut rows);
+    rows
+  }
+
+  #[test]
+  fn opening_a_comment_stops_at_until_and_leaves_the_next_row_stale() {
+    let syntax = RustHighlight::new();
+    let mut rows = rust_rows(&"let x = 1;\n".repeat(10));
+    rows[0].insert_character(0, '*');
+    rows[0].insert_character(0, '/');
+    syntax.update_syntax(0, &mut rows, 4);
+    assert!(rows[..4].iter().all(|row| row.is_comment));
+    assert!(rows[4].stale && !rows[4].is_comment);
+    assert!(rows[5..].iter().all(|row| !row.stale && !row.is_comment));
+
+    syntax.highlight_stale(&mut rows, 7);
+    assert!(rows[..7].iter().all(|row| row.is_comment && !row.stale));
+    assert!(rows[7].stale && !rows[7].is_comment);
+  }
+
+  #[test]
+  fn editing_above_a_stale_row_keeps_it_stale() {
+    let syntax = RustHighlight::new();
+    let mut rows = rust_rows(&"let x = 1;\n".repeat(10));
+    rows[5].stale = true;
+    rows[1].insert_character(0, 'x');
+    syntax.update_syntax(1, &mut rows, 4);
+    assert!(rows[5].stale);
+  }
+}
@@ -0,0 +1,52 @@
+// Earlier entries of a prompt, recalled with Up and Down
+use std::collections::VecDeque;
+
+const MAX_ENTRIES: usize = 50;
+
+pub struct History {
+  entries: VecDeque<String>, // Oldest first
+  position: Option<usize>, // The entry being recalled, `None` while typing a new one
+}
+
+impl History {
+  pub fn new() -> Self {
+    Self {
+      entries: VecDeque::with_capacity(MAX_ENTRIES),
+      position: None,
+    }
+  }
+
+  // Keeps an entered line, dropping the oldest once full
+  pub fn push(&mut self, entry: &str) {
+    self.position = None;
+    if entry.is_empty() || self.entries.back().is_some_and(|last| last == entry) {
+      return;
+    }
+    if self.entries.len() == MAX_ENTRIES {
+      self.entries.pop_front();
+    }
+    self.entries.push_back(entry.to_string());
+  }
+
+  // Up, `None` once there is nothing older
+  pub fn older(&mut self) -> Option<&str> {
+    let position = match self.position {
+      Some(0) => return None,
+      Some(position) => position - 1,
+      None => self.entries.len().checked_sub(1)?,
+    };
+    self.position = Some(position);
+    self.entries.get(position).map(String::as_str)
+  }
+
+  // Down, an empty line after the newest entry and `None` when nothing is recalled
+  pub fn newer(&mut self) -> Option<&str> {
+    let position = self.position?;
+    self.position = Some(position + 1).filter(|&position| position < self.entries.len());
+    Some(self.position.and_then(|position| self.entries.get(position)).map_or("", String::as_str))
+  }
+
+  pub fn reset(&mut self) {
+    self.position = None;
+  }
+}
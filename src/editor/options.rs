@@ -0,0 +1,92 @@
+use std::env;
+use crate::CONFIG;
+
+// Runtime settings changed with `:set`
+pub struct Options {
+  pub number: bool,
+  pub relative_number: bool,
+  pub readonly: bool,
+  pub expand_tab: bool,
+  pub wrap: bool,
+  pub backup: bool,
+  pub list: bool,
+  pub cursor_line: bool,
+  pub autopair: bool,
+  pub spell: bool,
+  pub ignore_case: bool,
+  pub smart_case: bool, // Only ignore case while the search term is all lowercase
+  pub regex: bool, // Search terms are regular expressions
+  pub text_width: usize, // Where `gq` wraps lines
+}
+
+impl Options {
+  pub fn new() -> Self {
+    Self {
+      number: true,
+      relative_number: false,
+      readonly: false,
+      expand_tab: false,
+      wrap: false,
+      backup: CONFIG.backup,
+      list: false,
+      cursor_line: false,
+      autopair: false,
+      spell: false,
+      ignore_case: false,
+      smart_case: false,
+      regex: false,
+      text_width: 80,
+    }
+  }
+
+  // Options that can also be given on the command line, like `-R`
+  pub fn from_args() -> Self {
+    let mut options = Self::new();
+    options.readonly = env::args().skip(1).any(|arg| arg == "-R" || arg == "--readonly");
+    options
+  }
+
+  // The gutter shows with either kind of line number, like vim's
+  pub fn line_numbers(&self) -> bool {
+    self.number || self.relative_number
+  }
+
+  // The number beside `file_row`. With `relativenumber` it's the distance from the
+  // cursor's row, which shows 0, or its own number when `number` is on too
+  pub fn line_number(&self, file_row: usize, cursor_y: usize) -> usize {
+    if !self.relative_number || (file_row == cursor_y && self.number) {
+      file_row + 1
+    } else {
+      file_row.abs_diff(cursor_y)
+    }
+  }
+
+  // Handles `name`, `noname` and `name!` (toggle) for the boolean options
+  pub fn set(&mut self, argument: &str) -> Result<(), String> {
+    let (name, value) = if let Some(name) = argument.strip_suffix('!') {
+      (name, None)
+    } else if let Some(name) = argument.strip_prefix("no") {
+      (name, Some(false))
+    } else {
+      (argument, Some(true))
+    };
+    let option = match name {
+      "nu" | "number" => &mut self.number,
+      "rnu" | "relativenumber" => &mut self.relative_number,
+      "ro" | "readonly" => &mut self.readonly,
+      "et" | "expandtab" => &mut self.expand_tab,
+      "wrap" => &mut self.wrap,
+      "bk" | "backup" => &mut self.backup,
+      "list" => &mut self.list,
+      "cul" | "cursorline" => &mut self.cursor_line,
+      "ap" | "autopair" => &mut self.autopair,
+      "spell" => &mut self.spell,
+      "ic" | "ignorecase" => &mut self.ignore_case,
+      "scs" | "smartcase" => &mut self.smart_case,
+      "re" | "regex" => &mut self.regex,
+      _ => return Err(format!("Unknown option: {}", argument)),
+    };
+    *option = value.unwrap_or(!*option);
+    Ok(())
+  }
+}
@@ -1,4 +1,5 @@
-use std::{io, cmp};
+use std::{io, cmp, mem, ops::Range, path::{Path, PathBuf}, time::Instant};
+use std::collections::HashMap;
 use std::io::Write;
 use crossterm::{cursor, event, execute, terminal, queue, style};
 use crossterm::event::{KeyCode, KeyEvent};
@@ -7,66 +8,212 @@ use crate::{
   log,
   prompt,
   CONFIG,
+  InputSource,
   Reader,
 };
 use super::{
+  buffers::{self, Buffer, Buffers},
+  clipboard::Clipboard,
+  history::History,
+  state,
+  swap,
   cursor::CursorController,
   editor::{
     EditorContents,
     EditorRows,
+    Row,
     StatusMessage,
   },
   highlight::{
     HighlightType,
     SyntaxHighlight,
   },
+  options::Options,
+  shell,
+  spell,
+  substitute::Substitution,
+  search,
+  text,
   syntax::{
     RustHighlight,
     PlainTextHighlight,
     JavaScriptHighlight,
     ShellScriptHighlight,
+    PythonHighlight,
+    JsonHighlight,
+    MarkdownHighlight,
+    GoHighlight,
+    CHighlight,
+    TomlHighlight,
+    MakefileHighlight,
+    DockerfileHighlight,
+    YamlHighlight,
   }
 };
 
+type Position = (usize, usize); // cursor_y, cursor_x
+
+// Opening and closing characters for `:set autopair`
+const PAIRS: [(char, char); 5] = [('(', ')'), ('[', ']'), ('{', '}'), ('"', '"'), ('\'', '\'')];
+
+// A dark grey behind the cursor's line with `:set cursorline`
+const CURSOR_LINE_COLOR: style::Color = style::Color::AnsiValue(236);
+
+const MAX_JUMPS: usize = 100;
+
 pub struct Output {
   pub window_size: (usize, usize), // screen_columns: 0, screen_rows: 1
+  pub input: Box<dyn InputSource>,
   pub editor_contents: EditorContents,
   pub editor_rows: EditorRows,
-  pub cursor_controller: CursorController,
+  pub cursor_controller: CursorController, // The active pane's
+  // Every pane's view of the buffer, the active one's is refreshed from `cursor_controller`
+  panes: Vec<CursorController>,
+  active_pane: usize,
   pub status_message: StatusMessage,
   pub dirty: bool,
   search_index: SearchIndex,
+  pub last_search: Option<String>, // Every match stays highlighted until `:noh`
   pub syntax_highlight: Option<Box<dyn SyntaxHighlight>>,
+  pub selection_anchor: Option<(usize, usize)>, // cursor_y, cursor_x
+  pub cursor_style: cursor::SetCursorStyle, // Set by the editor to match the mode
+  pub register: String,
+  pub clipboard: Clipboard,
+  pub options: Options,
+  swap_written: Instant,
+  buffers: Buffers,
+  pub command_history: History,
+  pub prompt_history: HashMap<&'static str, History>, // Keyed by the prompt's text
 }
 
 impl Output {
   pub fn new() -> Self {
-    let window_size = terminal::size()
-      .map(|(x, y)| (x as usize, y as usize - 2))
-      .unwrap();
+    let (columns, rows) = terminal::size().unwrap();
+    let mut output = Self::with_io(Box::new(Reader), Box::new(io::stdout()), columns, rows);
 
     let mut syntax_highlight = None;
+    // A file that can't be opened leaves an empty buffer rather than stopping the editor
+    match EditorRows::new(&mut syntax_highlight) {
+      Ok(editor_rows) => {
+        output.editor_rows = editor_rows;
+        output.syntax_highlight = syntax_highlight;
+      },
+      Err(error) => output.status_message.set_message(error.to_string()),
+    }
+    output.options = Options::from_args();
+    output.restore_cursor();
+    if let (_, Some(line)) = EditorRows::file_argument() {
+      output.go_to_line(line);
+      output.move_to_first_non_blank();
+    }
+    output
+  }
+
+  // An empty buffer reading from `input` and drawing to `sink`, which needn't be a terminal
+  pub fn with_io(input: Box<dyn InputSource>, sink: Box<dyn io::Write>, columns: u16, rows: u16) -> Self {
+    let window_size = Self::window_size(columns, rows);
     Self {
       window_size,
-      editor_contents: EditorContents::new(),
-      editor_rows: EditorRows::new(&mut syntax_highlight),
+      input,
+      editor_contents: EditorContents::new(sink),
+      editor_rows: EditorRows::empty(),
       cursor_controller: CursorController::new(window_size),
+      panes: vec![CursorController::new(window_size)],
+      active_pane: 0,
       status_message: StatusMessage::new("[COMMAND]".into()), // Starting in Command mode
       dirty: false,
       search_index: SearchIndex::new(),
-      syntax_highlight,
+      last_search: None,
+      syntax_highlight: None,
+      selection_anchor: None,
+      cursor_style: cursor::SetCursorStyle::SteadyBlock, // Starting in Command mode
+      register: String::new(),
+      clipboard: Clipboard::new(),
+      options: Options::new(),
+      swap_written: Instant::now(),
+      buffers: Buffers::new(),
+      command_history: History::new(),
+      prompt_history: HashMap::new(),
     }
   }
 
-  pub fn select_syntax(extension: &str) -> Option<Box<dyn SyntaxHighlight>> {
+  fn window_size(columns: u16, rows: u16) -> (usize, usize) {
+    // Leave room for the status and message bars
+    (columns as usize, (rows as usize).saturating_sub(2))
+  }
+
+  pub fn resize(&mut self, columns: u16, rows: u16) {
+    self.window_size = Self::window_size(columns, rows);
+    self.layout_panes();
+    self.cursor_controller.scroll(&self.editor_rows, self.options.wrap);
+  }
+
+  // Picks a highlighter by the file's name, then by its extension, trying the longest
+  // first so `x.d.ts` could match `d.ts` before `ts`
+  pub fn select_syntax(file: &Path) -> Option<Box<dyn SyntaxHighlight>> {
+    let name = file.file_name()?.to_str()?;
     let list: Vec<Box<dyn SyntaxHighlight>> = vec![
       Box::new(RustHighlight::new()),
       Box::new(PlainTextHighlight::new()),
       Box::new(JavaScriptHighlight::new()),
       Box::new(ShellScriptHighlight::new()),
+      Box::new(PythonHighlight::new()),
+      Box::new(JsonHighlight::new()),
+      Box::new(MarkdownHighlight::new()),
+      Box::new(GoHighlight::new()),
+      Box::new(CHighlight::new()),
+      Box::new(TomlHighlight::new()),
+      Box::new(MakefileHighlight::new()),
+      Box::new(DockerfileHighlight::new()),
+      Box::new(YamlHighlight::new()),
     ];
-    list.into_iter()
-      .find(|it| it.extensions().contains(&extension))
+    if let Some(index) = list.iter().position(|it| it.filenames().contains(&name)) {
+      return list.into_iter().nth(index);
+    }
+    // Everything after each `.`, so `archive.tar.gz` tries `tar.gz` and then `gz`
+    let extensions: Vec<&str> = name.match_indices('.').map(|(at, _)| &name[at + 1..]).collect();
+    let index = extensions
+      .iter()
+      .find_map(|extension| list.iter().position(|it| it.extensions().contains(extension)))?;
+    list.into_iter().nth(index)
+  }
+
+  // The term as typed, matched by `regex`, `ignorecase` and `smartcase`. Case is
+  // ignored unless `smartcase` is on too and the term has a capital letter in it
+  pub fn search_pattern(&self, keyword: &str) -> Result<search::Pattern, String> {
+    let ignore_case = self.options.ignore_case
+      && !(self.options.smart_case && keyword.chars().any(char::is_uppercase));
+    search::Pattern::new(keyword, ignore_case, self.options.regex)
+  }
+
+  fn search_forward(&self, pattern: &search::Pattern, y: usize, x: usize) -> Option<(usize, Range<usize>, bool)> {
+    // Returns the row, the match's byte range and whether the search wrapped past the bottom
+    let number_of_rows = self.editor_rows.number_of_rows();
+    (0..=number_of_rows).find_map(|i| {
+      let row_index = (y + i) % number_of_rows;
+      let render = self.editor_rows.get_render(row_index);
+      let start = if i == 0 { cmp::min(x, render.len()) } else { 0 };
+      pattern
+        .matches(render)
+        .into_iter()
+        .find(|range| range.start >= start)
+        .map(|range| (row_index, range, y + i >= number_of_rows))
+    })
+  }
+
+  fn search_backward(&self, pattern: &search::Pattern, y: usize, x: usize) -> Option<(usize, Range<usize>, bool)> {
+    // Returns the row, the match's byte range and whether the search wrapped past the top
+    let number_of_rows = self.editor_rows.number_of_rows();
+    (0..=number_of_rows).find_map(|i| {
+      let row_index = (y + number_of_rows - i % number_of_rows) % number_of_rows;
+      let render = self.editor_rows.get_render(row_index);
+      let end = if i == 0 { cmp::min(x, render.len()) } else { render.len() };
+      pattern
+        .matches(render)
+        .into_iter()
+        .rfind(|range| range.end <= end)
+        .map(|range| (row_index, range, i > y))
+    })
   }
 
   fn find_callback(output: &mut Output, keyword: &str, key_code: KeyCode) {
@@ -75,83 +222,66 @@ impl Output {
     }
     match key_code {
       KeyCode::Enter | KeyCode::Esc => {
+        if let (KeyCode::Enter, Some(message)) = (key_code, output.search_index.wrapped.take()) {
+          output.status_message.set_message(message.to_string());
+        }
+        if key_code == KeyCode::Enter {
+          match output.search_pattern(keyword) {
+            Ok(_) => output.last_search = Some(keyword.to_string()),
+            // Nothing was found while typing, this says why
+            Err(error) => output.status_message.set_message(error),
+          }
+        }
         output.search_index.reset();
       },
       _ => {
-        output.search_index.y_direction = None;
-        output.search_index.x_direction = None;
-        match key_code {
-          KeyCode::Down => {
-            output.search_index.y_direction = SearchDirection::Forward.into()
-          },
-          KeyCode::Up => {
-            output.search_index.y_direction = SearchDirection::Backward.into()
-          },
-          KeyCode::Left => {
-            output.search_index.x_direction = SearchDirection::Backward.into()
-          },
-          KeyCode::Right => {
-            output.search_index.x_direction = SearchDirection::Forward.into()
-          },
-          _ => {},
+        if keyword.is_empty() || output.editor_rows.number_of_rows() == 0 {
+          return;
         }
-        for i in 0..output.editor_rows.number_of_rows() {
-          let row_index = match output.search_index.y_direction.as_ref() {
-            None => {
-              if output.search_index.x_direction.is_none() {
-                output.search_index.y_index = i;
-              }
-              output.search_index.y_index
-            },
-            Some(direction) => {
-              if matches!(direction, SearchDirection::Forward) {
-                output.search_index.y_index + i + 1
-              } else {
-                let res = output.search_index.y_index.saturating_sub(i);
-                if res == 0 {
-                  break;
-                }
-                res - 1
-              }
-            }
-          };
-          if row_index > output.editor_rows.number_of_rows() - 1 {
-            break;
-          }
+        // A regex that doesn't compile, often one still being typed, matches nothing
+        let Ok(pattern) = output.search_pattern(keyword) else {
+          return;
+        };
+        let (y_index, x_index) = (output.search_index.y_index, output.search_index.x_index);
+        let direction = match key_code {
+          KeyCode::Down | KeyCode::Right => Some(SearchDirection::Forward),
+          KeyCode::Up | KeyCode::Left => Some(SearchDirection::Backward),
+          _ => None,
+        };
+        let found = match direction {
+          // While typing, stay on the current match for as long as it still fits
+          None => output.search_forward(&pattern, y_index, x_index),
+          Some(SearchDirection::Forward) => {
+            let render = output.editor_rows.get_render(y_index);
+            let next = x_index + render[x_index..].chars().next().map_or(1, char::len_utf8);
+            output.search_forward(&pattern, y_index, next)
+          },
+          Some(SearchDirection::Backward) => output.search_backward(&pattern, y_index, x_index),
+        };
+
+        if let Some((row_index, range, wrapped)) = found {
+          output.search_index.wrapped = wrapped.then_some(match direction {
+            Some(SearchDirection::Backward) => "search hit TOP, continuing at BOTTOM",
+            _ => "search hit BOTTOM, continuing at TOP",
+          });
+          // The match's row may have been left stale off screen, it's highlighted before
+          // being saved so putting the highlight back doesn't bring old colors with it
+          output.highlight_stale(row_index + 1);
           let row = output.editor_rows.get_editor_row_mut(row_index);
-          let index = match output.search_index.x_direction.as_ref() {
-            None => row.render.find(&keyword),
-            Some(direction) => {
-              let index = if matches!(direction, SearchDirection::Forward) {
-                let start = cmp::min(row.render.len(), output.search_index.x_index + 1);
-                row.render[start..]
-                  .find(&keyword)
-                  .map(|x| x + start)
-              } else {
-                row.render[..output.search_index.x_index]
-                  .rfind(&keyword)
-              };
-              if index.is_none() {
-                break;
-              }
-              index
-            }
-          };
-          if let Some(index) = index {
-            output.search_index.previous_highlight = Some((
-              row_index,
-              row.highlight.clone(),
-            ));
-            (index..index + keyword.len())
-              .for_each(|index| row.highlight[index] = HighlightType::SearchMatch);
-
-            output.cursor_controller.cursor_y = row_index;
-            output.search_index.y_index = row_index;
-            output.search_index.x_index = index;
-            output.cursor_controller.cursor_x = row.get_row_content_x(index);
-            output.cursor_controller.row_offset = output.editor_rows.number_of_rows();
-            break;
+          output.search_index.previous_highlight = Some((
+            row_index,
+            row.highlight.clone(),
+          ));
+          // Rows of a buffer without a highlighter have nothing to mark
+          if let Some(highlight) = row.highlight.get_mut(range.clone()) {
+            highlight.fill(HighlightType::SearchMatch);
           }
+
+          output.cursor_controller.cursor_y = row_index;
+          output.search_index.y_index = row_index;
+          output.search_index.x_index = range.start;
+          output.cursor_controller.cursor_x = row.get_row_content_x(text::width(&row.render[..range.start]));
+          output.cursor_controller.row_offset = output.editor_rows.number_of_rows();
         }
       }
     }
@@ -167,70 +297,88 @@ impl Output {
   //   self.dirty = true;
   // }
 
+  // `:noh`, dropping every search highlight along with the match left over from `find`
+  pub fn clear_search_highlight(&mut self) {
+    if let Some((index, highlight)) = self.search_index.previous_highlight.take() {
+      if index < self.editor_rows.number_of_rows() {
+        self.editor_rows.get_editor_row_mut(index).highlight = highlight;
+      }
+    }
+    self.search_index.reset();
+    self.last_search = None;
+  }
+
   pub fn find(&mut self) -> io::Result<()> {
     let cursor_controller = self.cursor_controller;
+    // Start searching from the cursor so the nearest match comes first
+    if cursor_controller.cursor_y < self.editor_rows.number_of_rows() {
+      let row = self.editor_rows.get_editor_row(cursor_controller.cursor_y);
+      self.search_index.y_index = cursor_controller.cursor_y;
+      self.search_index.x_index = row.render_byte_index(row.render_column(cursor_controller.cursor_x));
+    }
     if prompt!(
       self,
       "Search: {} (ESC to cancel)",
       callback = Output::find_callback
     ).is_none() {
       self.cursor_controller = cursor_controller;
+    } else {
+      self.push_jump((cursor_controller.cursor_y, cursor_controller.cursor_x));
     }
     Ok(())
   }
 
+  // Read-only buffers refuse every edit
+  pub fn is_modifiable(&mut self) -> bool {
+    if self.options.readonly {
+      self.status_message.set_message("E45: 'readonly' option is set".to_string());
+    }
+    !self.options.readonly
+  }
+
   pub fn insert_newline(&mut self) {
+    if !self.is_modifiable() {
+      return;
+    }
+    let mut cursor_x = 0;
     if self.cursor_controller.cursor_x == 0 {
       self.editor_rows
         .insert_row(self.cursor_controller.cursor_y, String::new())
     } else {
-      let indent = " ".repeat(
-        self
-          .editor_rows
-          .get_editor_row(self.cursor_controller.cursor_y)
-          .indent()
-        );
-
       let current_row = self
         .editor_rows
         .get_editor_row_mut(self.cursor_controller.cursor_y);
+      let split_index = current_row.byte_index(self.cursor_controller.cursor_x);
 
-      let new_row_content: String = current_row
-        .row_content[self.cursor_controller.cursor_x..]
-        .into();
+      // Carry the leading whitespace over as written, so tabs stay tabs
+      let indentation_length = cmp::min(current_row.indentation().len(), split_index);
+      let indentation = current_row.row_content[..indentation_length].to_string();
+      cursor_x = indentation.chars().count();
 
-      let indented_new_row_content = format!("{}{}", indent, new_row_content);
+      let indented_new_row_content = format!("{}{}", indentation, &current_row.row_content[split_index..]);
 
-      log::log::log("INFO".to_string(), format!("new_row_content: {}", indented_new_row_content));
+      log::log::log("DEBUG".to_string(), format!("new_row_content: {}", indented_new_row_content));
 
       current_row
         .row_content
-        .truncate(self.cursor_controller.cursor_x);
+        .truncate(split_index);
 
       EditorRows::render_row(current_row);
       self.editor_rows
         .insert_row(self.cursor_controller.cursor_y + 1, indented_new_row_content);
 
-      if let Some(it) = self.syntax_highlight.as_ref() {
-        it.update_syntax(
-          self.cursor_controller.cursor_y,
-          &mut self.editor_rows.row_contents,
-        );
-        it.update_syntax(
-          self.cursor_controller.cursor_y + 1,
-          &mut self.editor_rows.row_contents,
-        )
-      }
+      self.update_syntax(self.cursor_controller.cursor_y);
+      self.update_syntax(self.cursor_controller.cursor_y + 1);
     }
-    self.cursor_controller.cursor_x = self
-      .editor_rows
-      .get_editor_row(self.cursor_controller.cursor_y)
-      .indent();
+    self.cursor_controller.cursor_x = cursor_x;
     self.cursor_controller.cursor_y += 1;
     self.dirty = true;
   }
   
   pub fn insert_character(&mut self, character: char) {
+    if !self.is_modifiable() {
+      return;
+    }
     if self.cursor_controller.cursor_y == self.editor_rows.number_of_rows() {
       self.editor_rows
         .insert_row(self.editor_rows.number_of_rows(), String::new());
@@ -240,26 +388,1001 @@ impl Output {
       .get_editor_row_mut(self.cursor_controller.cursor_y)
       .insert_character(self.cursor_controller.cursor_x, character);
 
+    self.update_syntax(self.cursor_controller.cursor_y);
+
+    self.cursor_controller.cursor_x += 1;
+    self.dirty = true;
+  }
+
+  // Swaps the word before the cursor for its abbreviation from the config, as a separator
+  // is typed after it. A `$0` in the expansion is where the cursor ends up, and then
+  // the separator is left out since it would land there too. Returns whether it was
+  pub fn expand_abbreviation(&mut self) -> bool {
+    let (cursor_y, cursor_x) = (self.cursor_controller.cursor_y, self.cursor_controller.cursor_x);
+    if cursor_y >= self.editor_rows.number_of_rows() || !self.is_modifiable() {
+      return false;
+    }
+    let row = self.editor_rows.get_editor_row(cursor_y);
+    let before = &row.row_content[..row.byte_index(cursor_x)];
+    let trigger = before.rsplit(text::is_separator).next().unwrap_or_default();
+    let Some(expansion) = CONFIG.abbreviations.get(trigger) else {
+      return false;
+    };
+    log::log::log("INFO".to_string(), format!("Expanding {:?}", trigger));
+    let start = cursor_x - trigger.chars().count();
+    self.editor_rows.delete_text((cursor_y, start), (cursor_y, cursor_x));
+    self.cursor_controller.cursor_x = start;
+
+    let (text, after_cursor) = match expansion.split_once("$0") {
+      Some((text, after_cursor)) => (text, Some(after_cursor)),
+      None => (expansion.as_str(), None),
+    };
+    self.insert_expansion(text);
+    let Some(after_cursor) = after_cursor else {
+      return false;
+    };
+    let cursor = (self.cursor_controller.cursor_y, self.cursor_controller.cursor_x);
+    self.insert_expansion(after_cursor);
+    (self.cursor_controller.cursor_y, self.cursor_controller.cursor_x) = cursor;
+    true
+  }
+
+  fn insert_expansion(&mut self, text: &str) {
+    text.chars().for_each(|c| match c {
+      '\n' => self.insert_newline(),
+      c => self.insert_character(c),
+    });
+  }
+
+  // With `:set autopair`, an opening bracket or quote brings its closing one along with
+  // the cursor between them, and a closing one that's already next is stepped over.
+  // Inside strings and comments, and for quotes ending a word, characters go in as typed
+  pub fn insert_paired(&mut self, character: char) {
+    // Nothing is inserted in a read-only buffer, so there is no pair to step back into
+    if !self.is_modifiable() {
+      return;
+    }
+    let (cursor_y, cursor_x) = (self.cursor_controller.cursor_y, self.cursor_controller.cursor_x);
+    if cursor_y >= self.editor_rows.number_of_rows() {
+      self.insert_character(character);
+      return;
+    }
+    let row = self.editor_rows.get_editor_row(cursor_y);
+    let mut chars = row.row_content.chars().skip(cursor_x.saturating_sub(1));
+    let previous = if cursor_x > 0 { chars.next() } else { None };
+    let next = chars.next();
+    if next == Some(character) && PAIRS.iter().any(|&(_, close)| close == character) {
+      self.cursor_controller.cursor_x += 1;
+      return;
+    }
+    let pair = PAIRS.iter().find(|&&(open, _)| open == character);
+    let ends_word = character != '(' && character != '[' && character != '{'
+      && previous.is_some_and(char::is_alphanumeric);
+    match pair {
+      Some(&(open, close)) if !ends_word && !Self::in_string_or_comment(row, cursor_x) => {
+        self.insert_character(open);
+        self.insert_character(close);
+        self.cursor_controller.cursor_x -= 1;
+      },
+      _ => self.insert_character(character),
+    }
+  }
+
+  // Judged by the character before the cursor, where a quote that closes a string
+  // leaves the cursor outside it
+  fn in_string_or_comment(row: &Row, cursor_x: usize) -> bool {
+    let Some(previous_x) = cursor_x.checked_sub(1) else {
+      return false;
+    };
+    let highlight_at = |x: usize| row.highlight.get(row.render_byte_index(row.render_column(x)));
+    match highlight_at(previous_x) {
+      Some(HighlightType::Comment | HighlightType::MultilineComment) => true,
+      Some(string @ (HighlightType::DoubleQuoteString | HighlightType::SingleQuoteString)) => {
+        let is_quote = matches!(row.row_content.chars().nth(previous_x), Some('"' | '\''));
+        let string_started_before = previous_x > 0 && highlight_at(previous_x - 1)
+          .is_some_and(|before| mem::discriminant(before) == mem::discriminant(string));
+        !(is_quote && string_started_before)
+      },
+      _ => false,
+    }
+  }
+
+  // Backspace between an empty pair like `(|)` takes the closing character too
+  pub fn delete_closing_pair(&mut self) {
+    let (cursor_y, cursor_x) = (self.cursor_controller.cursor_y, self.cursor_controller.cursor_x);
+    if cursor_x == 0 || cursor_y >= self.editor_rows.number_of_rows() {
+      return;
+    }
+    let mut chars = self.editor_rows.get_editor_row(cursor_y).row_content.chars().skip(cursor_x - 1);
+    let (previous, next) = (chars.next(), chars.next());
+    if PAIRS.iter().any(|&(open, close)| previous == Some(open) && next == Some(close)) {
+      self.delete_characters(1, false);
+      self.cursor_controller.cursor_x = cursor_x;
+    }
+  }
+
+  // With expandtab, Tab inserts spaces up to the next tab stop
+  pub fn insert_tab(&mut self) {
+    if !self.options.expand_tab {
+      self.insert_character('\t');
+      return;
+    }
+    let cursor_y = self.cursor_controller.cursor_y;
+    let render_x = if cursor_y < self.editor_rows.number_of_rows() {
+      self.editor_rows.get_editor_row(cursor_y).render_column(self.cursor_controller.cursor_x)
+    } else {
+      0
+    };
+    let spaces = text::tab_width(render_x, self.editor_rows.tab_stop);
+    (0..spaces).for_each(|_| self.insert_character(' '));
+  }
+
+  // `gq` in Command mode, the run of non-blank rows around the cursor. In a comment
+  // it's the run of commented rows, so the code around it is left alone
+  pub fn paragraph(&self) -> Option<Range<usize>> {
+    let number_of_rows = self.editor_rows.number_of_rows();
+    let cursor_y = self.cursor_controller.cursor_y;
+    if cursor_y >= number_of_rows {
+      return None;
+    }
+    let comment_start = self.syntax_highlight.as_ref().map_or("", |it| it.comment_start());
+    // Doc comments like `///` are kept apart from `//` ones around them
+    let leader = |at: usize| {
+      comment_leader(self.editor_rows.get_row(at).trim_start(), comment_start).map(str::trim_end)
+    };
+    let cursor_leader = leader(cursor_y);
+    let is_outside = |at: usize| {
+      self.editor_rows.get_row(at).trim().is_empty() || (cursor_leader.is_some() && leader(at) != cursor_leader)
+    };
+    if is_outside(cursor_y) {
+      return None;
+    }
+    let start = (0..cursor_y).rev().find(|&at| is_outside(at)).map_or(0, |at| at + 1);
+    let end = (cursor_y..number_of_rows).find(|&at| is_outside(at)).unwrap_or(number_of_rows);
+    Some(start..end)
+  }
+
+  // `gq`, rewrapping the rows' words to `textwidth` columns. The first row's indentation
+  // and comment leader, like `// ` or `/// `, start every new row, and rows holding only
+  // the leader stay as breaks between paragraphs
+  pub fn format_lines(&mut self, rows: Range<usize>) {
+    if !self.is_modifiable() {
+      return;
+    }
+    let rows = rows.start..cmp::min(rows.end, self.editor_rows.number_of_rows());
+    if rows.is_empty() {
+      return;
+    }
+    let first = self.editor_rows.get_row(rows.start);
+    let indentation = &first[..first.len() - first.trim_start().len()];
+    let comment_start = self.syntax_highlight.as_ref().map_or("", |it| it.comment_start());
+    let first_leader = comment_leader(first.trim_start(), comment_start);
+    let in_comment = first_leader.is_some();
+    let leader = match first_leader {
+      Some(comment) => format!("{}{} ", indentation, comment.trim_end()),
+      None => indentation.to_string(),
+    };
+    let mut leader_row = Row::new(leader.clone(), String::new());
+    leader_row.tab_stop = self.editor_rows.tab_stop;
+    let leader_width = leader_row.render_column(leader_row.char_count());
+
+    let mut lines: Vec<String> = Vec::new();
+    let mut line = String::new();
+    let mut line_width = 0;
+    for at in rows.clone() {
+      let row = self.editor_rows.get_row(at).trim_start();
+      // Each row's own leader comes off, however many marks or spaces it has
+      let row = match comment_leader(row, comment_start) {
+        Some(comment) if in_comment => &row[comment.len()..],
+        _ => row,
+      };
+      if row.trim().is_empty() {
+        if !line.is_empty() {
+          lines.push(mem::take(&mut line));
+        }
+        lines.push(leader.trim_end().to_string());
+        continue;
+      }
+      for word in row.split_whitespace() {
+        let word_width = text::width(word);
+        if !line.is_empty() && line_width + 1 + word_width > self.options.text_width {
+          lines.push(mem::take(&mut line));
+        }
+        if line.is_empty() {
+          line = format!("{}{}", leader, word);
+          line_width = leader_width + word_width;
+        } else {
+          line.push(' ');
+          line.push_str(word);
+          line_width += 1 + word_width;
+        }
+      }
+    }
+    if !line.is_empty() {
+      lines.push(line);
+    }
+
+    // Inserting before removing leaves marks on the old rows on the last formatted one
+    for (offset, line) in lines.iter().enumerate() {
+      self.editor_rows.insert_row(rows.start + offset, line.clone());
+    }
+    self.editor_rows.remove_rows(rows.start + lines.len()..rows.end + lines.len());
+    (rows.start..rows.start + lines.len()).for_each(|at| self.update_syntax(at));
+    // Like vim, the cursor ends up on the last row formatted
+    self.cursor_controller.cursor_y = rows.start + lines.len() - 1;
+    self.move_to_first_non_blank();
+    self.dirty = true;
+  }
+
+  // `>>` and `<<`, shifting each row by a tab or a soft tab's worth of spaces
+  pub fn shift_lines(&mut self, rows: Range<usize>, right: bool) {
+    if !self.is_modifiable() {
+      return;
+    }
+    let indent = if self.options.expand_tab {
+      " ".repeat(self.editor_rows.tab_stop)
+    } else {
+      "\t".to_string()
+    };
+    let rows = rows.start..cmp::min(rows.end, self.editor_rows.number_of_rows());
+    for at in rows {
+      let shifted = if right {
+        self.editor_rows.indent_row(at, &indent)
+      } else {
+        -(self.editor_rows.dedent_row(at) as isize)
+      };
+      if shifted == 0 {
+        continue;
+      }
+      // The cursor stays on the same character
+      if at == self.cursor_controller.cursor_y {
+        let cursor_x = &mut self.cursor_controller.cursor_x;
+        *cursor_x = cursor_x.saturating_add_signed(shifted);
+      }
+      self.update_syntax(at);
+      self.dirty = true;
+    }
+  }
+
+  // How many characters Backspace removes, a whole soft tab when in leading spaces
+  pub fn backspace_width(&self) -> usize {
+    let (cursor_y, cursor_x) = (self.cursor_controller.cursor_y, self.cursor_controller.cursor_x);
+    if !self.options.expand_tab || cursor_x == 0 || cursor_y >= self.editor_rows.number_of_rows() {
+      return 1;
+    }
+    let row = self.editor_rows.get_editor_row(cursor_y);
+    if row.row_content.chars().take(cursor_x).all(|c| c == ' ') {
+      (cursor_x - 1) % row.tab_stop + 1
+    } else {
+      1
+    }
+  }
+
+  // Puts the cursor back where it was when this file was last closed or saved
+  pub fn restore_cursor(&mut self) {
+    let Some((y, x)) = self.editor_rows.filename.as_deref().and_then(state::cursor_position) else {
+      return;
+    };
+    // The file may have shrunk since
+    let number_of_rows = self.editor_rows.number_of_rows();
+    self.cursor_controller.cursor_y = cmp::min(y, number_of_rows.saturating_sub(1));
+    self.cursor_controller.cursor_x = if number_of_rows == 0 {
+      0
+    } else {
+      cmp::min(x, self.editor_rows.get_editor_row(self.cursor_controller.cursor_y).char_count())
+    };
+  }
+
+  // Flushes unsaved changes to the swap file every `CONFIG.swap_interval`
+  pub fn update_swap(&mut self) {
+    if !self.dirty || CONFIG.swap_interval.is_zero() || self.swap_written.elapsed() < CONFIG.swap_interval {
+      return;
+    }
+    if let Some(file) = self.editor_rows.filename.as_deref() {
+      swap::write(file, &self.editor_rows.contents());
+    }
+    self.swap_written = Instant::now();
+  }
+
+  pub fn remove_swap(&self) {
+    if let Some(file) = self.editor_rows.filename.as_deref() {
+      swap::remove(file);
+    }
+  }
+
+  // Takes the text of a swap file left behind by a crash, still unsaved
+  pub fn recover(&mut self, contents: &str) {
+    self.editor_rows.replace_contents(contents, &self.syntax_highlight);
+    self.dirty = true;
+    self.restore_cursor();
+  }
+
+  pub fn remember_cursor(&self) {
+    if let Some(file) = self.editor_rows.filename.as_deref() {
+      state::save_cursor_position(file, (self.cursor_controller.cursor_y, self.cursor_controller.cursor_x));
+    }
+  }
+
+  // Keeps the current buffer when the file can't be opened, returning whether it was
+  pub fn open_file(&mut self, file: PathBuf) -> bool {
+    let Some(buffer) = self.load_buffer(file) else {
+      return false;
+    };
+    self.remember_cursor();
+    self.show_buffer(buffer);
+    self.restore_cursor();
+    true
+  }
+
+  fn load_buffer(&mut self, file: PathBuf) -> Option<Buffer> {
+    let mut syntax_highlight = None;
+    match EditorRows::from_file(file, &mut syntax_highlight) {
+      Ok(editor_rows) => Some(Buffer {
+        editor_rows,
+        cursor_controller: CursorController::new(self.window_size),
+        syntax_highlight,
+        dirty: false,
+      }),
+      Err(error) => {
+        self.status_message.set_message(error.to_string());
+        None
+      },
+    }
+  }
+
+  fn take_buffer(&mut self) -> Buffer {
+    Buffer {
+      editor_rows: mem::replace(&mut self.editor_rows, EditorRows::empty()),
+      cursor_controller: self.cursor_controller,
+      syntax_highlight: self.syntax_highlight.take(),
+      dirty: self.dirty,
+    }
+  }
+
+  fn show_buffer(&mut self, buffer: Buffer) {
+    self.editor_rows = buffer.editor_rows;
+    self.editor_rows.line_numbers = self.options.line_numbers();
+    // The buffer's cursor goes to the active pane, sized for it
+    self.cursor_controller = buffer.cursor_controller;
+    self.cursor_controller.clamp(&self.editor_rows);
+    self.layout_panes();
+    self.syntax_highlight = buffer.syntax_highlight;
+    self.dirty = buffer.dirty;
+    self.search_index.reset();
+    self.selection_anchor = None;
+  }
+
+  // `:e`, going to the file's buffer when it's already open and loading it into a new
+  // one otherwise. Returns whether the file was read from disk
+  pub fn edit_file(&mut self, file: PathBuf) -> bool {
+    if let Some(index) = self.buffers.position(&file) {
+      self.switch_to_buffer(index);
+      return false;
+    }
+    if self.editor_rows.filename.as_deref().is_some_and(|filename| buffers::same_file(filename, &file)) {
+      return false;
+    }
+    // An untouched unnamed buffer is reused rather than kept around
+    if self.editor_rows.filename.is_none() && !self.dirty && self.editor_rows.number_of_rows() == 0 {
+      return self.open_file(file);
+    }
+    let Some(buffer) = self.load_buffer(file) else {
+      return false;
+    };
+    self.remember_cursor();
+    let current = self.take_buffer();
+    self.buffers.push(current);
+    self.show_buffer(buffer);
+    self.restore_cursor();
+    true
+  }
+
+  // `:bn` and `:bp`, wrapping around the ends of the list
+  pub fn cycle_buffers(&mut self, offset: isize) {
+    let index = (self.buffers.current as isize + offset).rem_euclid(self.buffers.count() as isize);
+    self.switch_to_buffer(index as usize);
+  }
+
+  fn switch_to_buffer(&mut self, index: usize) {
+    if index != self.buffers.current {
+      self.remember_cursor();
+      let current = self.take_buffer();
+      let buffer = self.buffers.switch(current, index);
+      self.show_buffer(buffer);
+    }
+    self.status_message.set_message(format!(
+      "Buffer {} of {}: \"{}\"",
+      index + 1,
+      self.buffers.count(),
+      Self::buffer_name(&self.editor_rows),
+    ));
+  }
+
+  // `:bd`, showing the next buffer or an empty one when this was the last
+  pub fn close_buffer(&mut self) {
+    self.remember_cursor();
+    self.remove_swap();
+    let buffer = self.buffers.close().unwrap_or_else(|| Buffer {
+      editor_rows: EditorRows::empty(),
+      cursor_controller: CursorController::new(self.window_size),
+      syntax_highlight: None,
+      dirty: false,
+    });
+    self.show_buffer(buffer);
+  }
+
+  // `:w !command`, showing what the command prints for the buffer's text
+  pub fn pipe_buffer(&mut self, command: &str) {
+    let message = match shell::run(command, &self.editor_rows.contents()) {
+      Ok(output) => output.trim_end().lines().collect::<Vec<&str>>().join(" | "),
+      Err(message) => message,
+    };
+    self.status_message.set_message(message);
+  }
+
+  // `:%!command`, replacing the buffer with what the command prints for it
+  pub fn filter_buffer(&mut self, command: &str) {
+    if !self.is_modifiable() {
+      return;
+    }
+    match shell::run(command, &self.editor_rows.contents()) {
+      Ok(output) => {
+        self.editor_rows.replace_contents(&output, &self.syntax_highlight);
+        self.cursor_controller.clamp(&self.editor_rows);
+        self.dirty = true;
+        self.status_message.set_message(format!("{} lines filtered", self.editor_rows.number_of_rows()));
+      },
+      Err(message) => self.status_message.set_message(message),
+    }
+  }
+
+  // `:stats` and `g Ctrl-G`, counted from the contents as `save` would write them
+  pub fn show_stats(&mut self) {
+    let contents = self.editor_rows.contents();
+    let words = contents.split(text::is_separator).filter(|word| !word.is_empty()).count();
+    self.status_message.set_message(format!(
+      "{} lines, {} words, {} characters, {} bytes",
+      self.editor_rows.number_of_rows(),
+      words,
+      contents.chars().count(),
+      contents.len(),
+    ));
+  }
+
+  // `:ls`, marking the current buffer with `%` and unsaved ones with `+`
+  pub fn list_buffers(&mut self) {
+    let mut buffers: Vec<(usize, &EditorRows, bool)> = self.buffers
+      .others()
+      .map(|(index, buffer)| (index, &buffer.editor_rows, buffer.dirty))
+      .collect();
+    buffers.push((self.buffers.current, &self.editor_rows, self.dirty));
+    buffers.sort_by_key(|(index, _, _)| *index);
+    let list = buffers
+      .into_iter()
+      .map(|(index, editor_rows, dirty)| {
+        format!(
+          "{}{}{} \"{}\"",
+          index + 1,
+          if index == self.buffers.current { "%" } else { " " },
+          if dirty { "+" } else { " " },
+          Self::buffer_name(editor_rows),
+        )
+      })
+      .collect::<Vec<String>>()
+      .join(" | ");
+    self.status_message.set_message(list);
+  }
+
+  fn buffer_name(editor_rows: &EditorRows) -> String {
+    editor_rows.filename
+      .as_ref()
+      .map_or("[Untitled]".to_string(), |filename| filename.display().to_string())
+  }
+
+  // Whether any buffer, shown or not, has unsaved changes
+  pub fn any_dirty(&self) -> bool {
+    self.dirty || self.buffers.others().any(|(_, buffer)| buffer.dirty)
+  }
+
+  // `ZZ` and `:wq` only write the buffer on screen, so unsaved changes in any other one
+  // keep the editor open, with the first of them named like vim does
+  pub fn warn_hidden_changes(&mut self) -> bool {
+    let Some((index, buffer)) = self.buffers.others().find(|(_, buffer)| buffer.dirty) else {
+      return false;
+    };
+    let message = format!(
+      "E162: No write since last change for buffer {} \"{}\"",
+      index + 1,
+      Self::buffer_name(&buffer.editor_rows),
+    );
+    self.status_message.set_message(message);
+    true
+  }
+
+  // On quitting, every buffer's cursor is remembered and its swap file removed
+  pub fn close_all_buffers(&self) {
+    self.remember_cursor();
+    self.remove_swap();
+    for (_, buffer) in self.buffers.others() {
+      if let Some(file) = buffer.editor_rows.filename.as_deref() {
+        let cursor = buffer.cursor_controller;
+        state::save_cursor_position(file, (cursor.cursor_y, cursor.cursor_x));
+        swap::remove(file);
+      }
+    }
+  }
+
+  // Throws away the buffer and reads the file from disk again, keeping the cursor's line
+  pub fn reload(&mut self) {
+    let Some(file) = self.editor_rows.filename.clone() else {
+      self.status_message.set_message("No file name.".to_string());
+      return;
+    };
+    let cursor_y = self.cursor_controller.cursor_y;
+    if !self.open_file(file) {
+      return;
+    }
+    self.go_to_line(cursor_y + 1);
+    self.status_message.set_message("File reloaded.".to_string());
+  }
+
+  // Returns the number of replacements made and how many rows they touched
+  pub fn substitute(&mut self, substitution: &Substitution, pattern: &search::Pattern, rows: Range<usize>) -> (usize, usize) {
+    let mut replacements = 0;
+    let mut changed_rows = 0;
+    for at in rows {
+      let row = self.editor_rows.get_editor_row_mut(at);
+      let (content, count) = substitution.apply(pattern, &row.row_content);
+      if count == 0 {
+        continue;
+      }
+      replacements += count;
+      changed_rows += 1;
+      self.cursor_controller.cursor_y = at;
+      row.row_content = content;
+      EditorRows::render_row(row);
+      self.update_syntax(at);
+    }
+    if replacements > 0 {
+      // Like vim, leave the cursor on the last line that changed
+      self.move_to_first_non_blank();
+      self.dirty = true;
+    }
+    (replacements, changed_rows)
+  }
+
+  // `:s///c`, moving to each match and asking before replacing it: y(es), n(o),
+  // a(ll the rest) or q(uit). Skipped matches aren't asked about again
+  pub fn substitute_confirm(&mut self, substitution: &Substitution, pattern: &search::Pattern, rows: Range<usize>) -> crossterm::Result<(usize, usize)> {
+    let mut replacements = 0;
+    let mut changed_rows = 0;
+    let mut replace_all = false;
+    // The next search starts after the text just skipped or put in, and a step further
+    // after an empty match so it isn't found again
+    let next_from = |line: &str, end: usize, empty: bool| {
+      end + if empty { line[end..].chars().next().map_or(1, char::len_utf8) } else { 0 }
+    };
+    'rows: for at in rows {
+      let mut from = 0;
+      let mut changed = false;
+      while let Some(range) = substitution.find(pattern, self.editor_rows.get_row(at), from) {
+        if !replace_all {
+          let row = self.editor_rows.get_editor_row_mut(at);
+          let cursor_x = row.row_content[..range.start].chars().count();
+          let render_range = row.render_byte_range(range.clone());
+          if let Some(highlight) = row.highlight.get_mut(render_range) {
+            highlight.fill(HighlightType::SearchMatch);
+          }
+          self.cursor_controller.cursor_y = at;
+          self.cursor_controller.cursor_x = cursor_x;
+
+          let answer = prompt!(self, "Replace this match? (y/n/a/q) {}");
+          self.update_syntax(at);
+          match answer.as_deref().and_then(|answer| answer.chars().next()) {
+            Some('y' | 'Y') => {},
+            Some('a' | 'A') => replace_all = true,
+            Some('n' | 'N') => {
+              from = next_from(self.editor_rows.get_row(at), range.end, range.is_empty());
+              continue;
+            },
+            _ => break 'rows,
+          }
+        }
+        let row = self.editor_rows.get_editor_row_mut(at);
+        row.row_content = substitution.replace_at(&row.row_content, range.clone());
+        EditorRows::render_row(row);
+        self.update_syntax(at);
+        replacements += 1;
+        changed = true;
+        from = next_from(self.editor_rows.get_row(at), range.start + substitution.replacement.len(), range.is_empty());
+        if !substitution.global {
+          break;
+        }
+      }
+      if changed {
+        changed_rows += 1;
+        self.cursor_controller.cursor_y = at;
+      }
+    }
+    if replacements > 0 {
+      self.move_to_first_non_blank();
+      self.dirty = true;
+    }
+    Ok((replacements, changed_rows))
+  }
+
+  pub fn set_option(&mut self, argument: &str) -> Result<(), String> {
+    log::log::log("INFO".to_string(), format!("Setting option: {}", argument));
+    match argument.split_once('=') {
+      Some(("ts" | "tabstop", value)) => {
+        let tab_stop = value
+          .parse()
+          .ok()
+          .filter(|tab_stop| *tab_stop > 0)
+          .ok_or_else(|| format!("Invalid tabstop: {}", value))?;
+        self.set_tab_stop(tab_stop);
+        Ok(())
+      },
+      Some(("tw" | "textwidth", value)) => {
+        self.options.text_width = value
+          .parse()
+          .ok()
+          .filter(|text_width| *text_width > 0)
+          .ok_or_else(|| format!("Invalid textwidth: {}", value))?;
+        Ok(())
+      },
+      // Whether the file ends with a line break belongs to the buffer, not the session
+      None if matches!(argument, "eol" | "endofline" | "noeol" | "noendofline" | "eol!" | "endofline!") => {
+        let end_of_line = &mut self.editor_rows.end_of_line;
+        *end_of_line = if argument.ends_with('!') { !*end_of_line } else { !argument.starts_with("no") };
+        self.dirty = true;
+        Ok(())
+      },
+      _ => {
+        self.options.set(argument)?;
+        if self.options.spell && !spell::available() {
+          self.options.spell = false;
+          return Err("No word list found, set spell_file in the config".to_string());
+        }
+        self.editor_rows.line_numbers = self.options.line_numbers();
+        self.cursor_controller.scroll(&self.editor_rows, self.options.wrap);
+        Ok(())
+      },
+    }
+  }
+
+  fn set_tab_stop(&mut self, tab_stop: usize) {
+    self.editor_rows.set_tab_stop(tab_stop);
+    if let Some(it) = self.syntax_highlight.as_ref() {
+      it.update_all_syntax(&mut self.editor_rows.row_contents)
+    }
+  }
+
+  // Re-highlights a row after an edit. Rows below the screen a comment change reaches
+  // are left stale, and `draw_pane` highlights them once they come into view
+  fn update_syntax(&mut self, at: usize) {
+    let until = self.cursor_controller.row_offset + self.cursor_controller.screen_rows;
     if let Some(it) = self.syntax_highlight.as_ref() {
-      it.update_syntax(
-        self.cursor_controller.cursor_y,
-        &mut self.editor_rows.row_contents,
+      it.update_syntax(at, &mut self.editor_rows.row_contents, until)
+    }
+  }
+
+  // Highlights the rows before `until` that `update_syntax` left stale
+  fn highlight_stale(&mut self, until: usize) {
+    if let Some(it) = self.syntax_highlight.as_ref() {
+      it.highlight_stale(&mut self.editor_rows.row_contents, until)
+    }
+  }
+
+  // Called before a search or line jump moves the cursor away. Newer positions
+  // Ctrl-O went back past are dropped, like a browser's history
+  pub fn record_jump(&mut self) {
+    self.push_jump((self.cursor_controller.cursor_y, self.cursor_controller.cursor_x));
+  }
+
+  fn push_jump(&mut self, position: (usize, usize)) {
+    let rows = &mut self.editor_rows;
+    rows.jump_list.truncate(rows.jump_index);
+    rows.jump_list.retain(|&(y, _)| y != position.0);
+    rows.jump_list.push(position);
+    if rows.jump_list.len() > MAX_JUMPS {
+      rows.jump_list.remove(0);
+    }
+    rows.jump_index = rows.jump_list.len();
+  }
+
+  // Ctrl-O goes back through the jump list and Ctrl-I (Tab) forward again
+  pub fn jump(&mut self, back: bool) {
+    let rows = &mut self.editor_rows;
+    if back {
+      if rows.jump_index == 0 {
+        return;
+      }
+      // Remember where going back started, so Ctrl-I can return to it
+      if rows.jump_index == rows.jump_list.len() {
+        let position = (self.cursor_controller.cursor_y, self.cursor_controller.cursor_x);
+        rows.jump_list.push(position);
+      }
+      rows.jump_index -= 1;
+    } else {
+      if rows.jump_index + 1 >= rows.jump_list.len() {
+        return;
+      }
+      rows.jump_index += 1;
+    }
+    let (cursor_y, cursor_x) = rows.jump_list[rows.jump_index];
+    self.cursor_controller.cursor_y = cursor_y;
+    self.cursor_controller.cursor_x = cursor_x;
+    // Lines may have been deleted since the jump
+    self.cursor_controller.clamp(&self.editor_rows);
+  }
+
+  pub fn set_mark(&mut self, name: char) {
+    self.editor_rows.marks.insert(name, (self.cursor_controller.cursor_y, self.cursor_controller.cursor_x));
+  }
+
+  // `` `a `` goes back to the mark's exact spot, `'a` to the first non-blank of its line.
+  // Lines may have been deleted since it was set, so the spot is kept inside the buffer
+  pub fn jump_to_mark(&mut self, name: char, line_start: bool) {
+    let Some(&(cursor_y, cursor_x)) = self.editor_rows.marks.get(&name) else {
+      self.status_message.set_message("E20: Mark not set".to_string());
+      return;
+    };
+    self.record_jump();
+    self.cursor_controller.cursor_y = cursor_y;
+    self.cursor_controller.cursor_x = cursor_x;
+    self.cursor_controller.clamp(&self.editor_rows);
+    if line_start {
+      self.move_to_first_non_blank();
+    }
+  }
+
+  pub fn go_to_line(&mut self, line: usize) {
+    // Lines are 1-based, anything past the end lands on the last line
+    let last_row = self.editor_rows.number_of_rows().saturating_sub(1);
+    self.cursor_controller.cursor_y = cmp::min(line.saturating_sub(1), last_row);
+    self.cursor_controller.cursor_x = 0;
+    self.cursor_controller.scroll(&self.editor_rows, self.options.wrap);
+  }
+
+  pub fn move_to_first_non_blank(&mut self) {
+    if self.cursor_controller.cursor_y < self.editor_rows.number_of_rows() {
+      self.cursor_controller.cursor_x = self
+        .editor_rows
+        .get_editor_row(self.cursor_controller.cursor_y)
+        .first_non_blank();
+    }
+  }
+
+  // Where `a` and `A` start inserting, after the cursor's character or at the end of the line
+  pub fn move_to_append(&mut self, end_of_line: bool) {
+    if self.cursor_controller.cursor_y < self.editor_rows.number_of_rows() {
+      let length = self.editor_rows.get_editor_row(self.cursor_controller.cursor_y).char_count();
+      self.cursor_controller.cursor_x = if end_of_line {
+        length
+      } else {
+        cmp::min(self.cursor_controller.cursor_x + 1, length)
+      };
+    }
+  }
+
+  pub fn selection(&self) -> Option<((usize, usize), (usize, usize))> {
+    // Ordered start and exclusive end, including the character under the cursor
+    self.selection_anchor.map(|anchor| {
+      let cursor = (self.cursor_controller.cursor_y, self.cursor_controller.cursor_x);
+      let (start, end) = if anchor <= cursor { (anchor, cursor) } else { (cursor, anchor) };
+      (start, (end.0, end.1 + 1))
+    })
+  }
+
+  fn selected_columns(&self, file_row: usize) -> Option<(usize, usize)> {
+    // Render columns of the selection that fall on the given row
+    let (start, end) = self.selection()?;
+    if file_row < start.0 || file_row > end.0 {
+      return None;
+    }
+    let row = self.editor_rows.get_editor_row(file_row);
+    let first = if file_row == start.0 { start.1 } else { 0 };
+    let last = if file_row == end.0 { end.1 } else { row.char_count() };
+    Some((row.render_column(first), row.render_column(last)))
+  }
+
+  pub fn yank_selection(&mut self) {
+    if let Some((start, end)) = self.selection() {
+      self.register = self.editor_rows.get_text(start, end);
+      self.clipboard.set(&self.register);
+      self.status_message
+        .set_message(format!("{} characters yanked", self.register.chars().count()));
+    }
+  }
+
+  pub fn paste(&mut self, from_clipboard: bool) {
+    if !self.is_modifiable() {
+      return;
+    }
+    let text = if from_clipboard {
+      self.clipboard.get().unwrap_or_else(|| self.register.clone())
+    } else {
+      self.register.clone()
+    };
+    if text.is_empty() {
+      self.status_message.set_message("Nothing to paste.".to_string());
+      return;
+    }
+    // Like vim, paste after the character under the cursor
+    let cursor_y = self.cursor_controller.cursor_y;
+    let cursor_x = if cursor_y < self.editor_rows.number_of_rows() {
+      cmp::min(
+        self.cursor_controller.cursor_x + 1,
+        self.editor_rows.get_editor_row(cursor_y).char_count(),
       )
+    } else {
+      0
+    };
+    let end = self.editor_rows.insert_text((cursor_y, cursor_x), &text);
+    (cursor_y..=end.0).for_each(|i| self.update_syntax(i));
+    self.cursor_controller.cursor_y = end.0;
+    self.cursor_controller.cursor_x = end.1.saturating_sub(1);
+    self.dirty = true;
+  }
+
+  pub fn delete_selection(&mut self) {
+    if !self.is_modifiable() {
+      return;
     }
+    if let Some((start, end)) = self.selection() {
+      if self.editor_rows.number_of_rows() == 0 {
+        return;
+      }
+      self.register = self.editor_rows.get_text(start, end);
+      self.clipboard.set(&self.register);
+      self.editor_rows.delete_text(start, end);
+      self.cursor_controller.cursor_y = start.0;
+      self.cursor_controller.cursor_x = start.1;
+      // The row after the join may have assumed a comment state that no longer holds
+      self.update_syntax(start.0);
+      self.update_syntax(start.0 + 1);
+      self.dirty = true;
+    }
+  }
 
-    self.cursor_controller.cursor_x += 1;
+  // `dw`, `de` and `db`, deleting from the cursor to where the word motion lands. Like
+  // vim, `de` takes the word's last character too, and `dw` stops at the end of the line
+  // unless the cursor is already there, when it joins the next line up to its first word
+  pub fn delete_word_motion(&mut self, motion: KeyCode, count: usize) {
+    if !self.is_modifiable() {
+      return;
+    }
+    let start = (self.cursor_controller.cursor_y, self.cursor_controller.cursor_x);
+    if start.0 >= self.editor_rows.number_of_rows() {
+      return;
+    }
+    let mut moved = self.cursor_controller;
+    (0..count).for_each(|_| moved.move_cursor(motion, &self.editor_rows));
+    let end = (moved.cursor_y, moved.cursor_x);
+    let row_length = |y: usize| self.editor_rows.get_editor_row(y).char_count();
+    let (from, to) = match motion {
+      KeyCode::Char('b') => (end, start),
+      KeyCode::Char('e') => (start, (end.0, cmp::min(end.1 + 1, row_length(end.0)))),
+      _ if end.0 > start.0 && start.1 < row_length(start.0) => (start, (end.0 - 1, row_length(end.0 - 1))),
+      _ => (start, end),
+    };
+    if from >= to {
+      return;
+    }
+    self.register = self.editor_rows.get_text(from, to);
+    self.editor_rows.delete_text(from, to);
+    (self.cursor_controller.cursor_y, self.cursor_controller.cursor_x) = from;
+    self.cursor_controller.clamp(&self.editor_rows);
+    self.update_syntax(from.0);
+    self.update_syntax(from.0 + 1);
     self.dirty = true;
   }
 
-  pub fn delete_line(&mut self) {
-    if self.cursor_controller.cursor_y == self.editor_rows.number_of_rows() {
+  // `dd`, deleting `count` rows from the cursor's down into the register
+  pub fn delete_lines(&mut self, count: usize) {
+    if !self.is_modifiable() {
+      return;
+    }
+    let cursor_y = self.cursor_controller.cursor_y;
+    let number_of_rows = self.editor_rows.number_of_rows();
+    if cursor_y >= number_of_rows {
+      return;
+    }
+    let end = cmp::min(cursor_y + count, number_of_rows);
+    self.register = (cursor_y..end).map(|at| format!("{}\n", self.editor_rows.get_row(at))).collect();
+    self.editor_rows.remove_rows(cursor_y..end);
+    self.cursor_controller.clamp(&self.editor_rows);
+    self.move_to_first_non_blank();
+    // The row moving up was highlighted after the comment state of the ones deleted
+    self.update_syntax(cursor_y);
+    self.dirty = true;
+  }
+
+  // `x` deletes from the cursor on, `X` the characters before it, never past the line
+  pub fn delete_characters(&mut self, count: usize, before: bool) {
+    if !self.is_modifiable() {
+      return;
+    }
+    let (cursor_y, cursor_x) = (self.cursor_controller.cursor_y, self.cursor_controller.cursor_x);
+    if cursor_y >= self.editor_rows.number_of_rows() {
+      return;
+    }
+    let char_count = self.editor_rows.get_editor_row(cursor_y).char_count();
+    let (start, end) = if before {
+      (cursor_x.saturating_sub(count), cursor_x)
+    } else {
+      (cursor_x, cmp::min(cursor_x + count, char_count))
+    };
+    if start >= end {
+      return;
+    }
+    self.register = self.editor_rows.get_text((cursor_y, start), (cursor_y, end));
+    self.editor_rows.delete_text((cursor_y, start), (cursor_y, end));
+    // Deleting the last character leaves the cursor on the new last one
+    self.cursor_controller.cursor_x = cmp::min(start, (char_count - (end - start)).saturating_sub(1));
+    self.update_syntax(cursor_y);
+    self.dirty = true;
+  }
+
+  // Like vim, does nothing unless there are `count` characters from the cursor on
+  pub fn replace_characters(&mut self, replacement: char, count: usize) {
+    if !self.is_modifiable() {
+      return;
+    }
+    let (cursor_y, cursor_x) = (self.cursor_controller.cursor_y, self.cursor_controller.cursor_x);
+    if cursor_y >= self.editor_rows.number_of_rows()
+      || cursor_x + count > self.editor_rows.get_editor_row(cursor_y).char_count() {
+      return;
+    }
+    let end = (cursor_y, cursor_x + count);
+    self.editor_rows.delete_text((cursor_y, cursor_x), end);
+    self.editor_rows.insert_text((cursor_y, cursor_x), &replacement.to_string().repeat(count));
+    self.cursor_controller.cursor_x = cursor_x + count - 1;
+    self.update_syntax(cursor_y);
+    self.dirty = true;
+  }
+
+  pub fn join_lines(&mut self, joins: usize) {
+    if !self.is_modifiable() {
+      return;
+    }
+    let cursor_y = self.cursor_controller.cursor_y;
+    for _ in 0..joins {
+      match self.editor_rows.join_with_next(cursor_y) {
+        Some(join) => self.cursor_controller.cursor_x = join,
+        None => break,
+      }
+      self.dirty = true;
+    }
+    self.update_syntax(cursor_y);
+    self.update_syntax(cursor_y + 1);
+  }
+
+  pub fn open_line(&mut self, below: bool) {
+    if !self.is_modifiable() {
       return;
     }
-    self.editor_rows.join_adjacent_rows(self.cursor_controller.cursor_y);
+    let number_of_rows = self.editor_rows.number_of_rows();
+    let cursor_y = cmp::min(self.cursor_controller.cursor_y, number_of_rows);
+    let indentation = if cursor_y < number_of_rows {
+      self.editor_rows.get_editor_row(cursor_y).indentation().to_string()
+    } else {
+      String::new()
+    };
+    let at = if below { cmp::min(cursor_y + 1, number_of_rows) } else { cursor_y };
+
+    self.cursor_controller.cursor_x = indentation.chars().count();
+    self.cursor_controller.cursor_y = at;
+    self.editor_rows.insert_row(at, indentation);
+    self.update_syntax(at);
     self.dirty = true;
   }
 
   pub fn delete_character(&mut self) {
+    if !self.is_modifiable() {
+      return;
+    }
     if self.cursor_controller.cursor_y == self.editor_rows.number_of_rows() {
       return;
     }
@@ -272,38 +1395,128 @@ impl Output {
       row.delete_character(self.cursor_controller.cursor_x - 1);
       self.cursor_controller.cursor_x -= 1;
     } else {
-      let previous_row_content = self
+      self.cursor_controller.cursor_x = self
         .editor_rows
-        .get_row(self.cursor_controller.cursor_y - 1);
-
-      self.cursor_controller.cursor_x = previous_row_content.len();
+        .get_editor_row(self.cursor_controller.cursor_y - 1)
+        .char_count();
       self.editor_rows
         .join_adjacent_rows(self.cursor_controller.cursor_y);
       self.cursor_controller.cursor_y -= 1;
     }
-    if let Some(it) = self.syntax_highlight.as_ref() {
-      it.update_syntax(
-        self.cursor_controller.cursor_y,
-        &mut self.editor_rows.row_contents,
-      );
-      // This block is causing a panic because the index equals the length of the row_contents (or something like that)
-      // it.update_syntax(
-      //   self.cursor_controller.cursor_y + 1,
-      //   &mut self.editor_rows.row_contents,
-      // )
-    }
+    self.update_syntax(self.cursor_controller.cursor_y);
+    // A joined row takes the place of one whose comment state the next row was built on
+    self.update_syntax(self.cursor_controller.cursor_y + 1);
     self.dirty = true;
   }
 
+  fn is_code(row: &Row, index: usize) -> bool {
+    !matches!(
+      row.highlight.get(index),
+      Some(
+        HighlightType::DoubleQuoteString
+        | HighlightType::SingleQuoteString
+        | HighlightType::Comment
+        | HighlightType::MultilineComment
+        | HighlightType::ObjectKey
+        | HighlightType::CodeSpan
+      )
+    )
+  }
+
+  // The bracket under the cursor and its partner, if it has one
+  fn matching_bracket(&self) -> Option<(Position, Option<Position>)> {
+    let (cursor_y, cursor_x) = (self.cursor_controller.cursor_y, self.cursor_controller.cursor_x);
+    if cursor_y >= self.editor_rows.number_of_rows() {
+      return None;
+    }
+    let row = self.editor_rows.get_editor_row(cursor_y);
+    let bracket = row.row_content.chars().nth(cursor_x)?;
+    let (partner, forward) = match bracket {
+      '(' => (')', true),
+      '[' => (']', true),
+      '{' => ('}', true),
+      ')' => ('(', false),
+      ']' => ('[', false),
+      '}' => ('{', false),
+      _ => return None,
+    };
+    if !Self::is_code(row, row.render_indices()[cursor_x]) {
+      return None;
+    }
+
+    let rows: Vec<usize> = if forward {
+      (cursor_y..self.editor_rows.number_of_rows()).collect()
+    } else {
+      (0..=cursor_y).rev().collect()
+    };
+    let mut depth = 0;
+    for y in rows {
+      let row = self.editor_rows.get_editor_row(y);
+      let characters: Vec<char> = row.row_content.chars().collect();
+      let indices = row.render_indices();
+      let mut columns: Vec<usize> = match (y == cursor_y, forward) {
+        (true, true) => (cursor_x..characters.len()).collect(),
+        (true, false) => (0..=cursor_x).collect(),
+        (false, _) => (0..characters.len()).collect(),
+      };
+      if !forward {
+        columns.reverse();
+      }
+      for x in columns {
+        if !Self::is_code(row, indices[x]) {
+          continue;
+        }
+        if characters[x] == bracket {
+          depth += 1;
+        } else if characters[x] == partner {
+          depth -= 1;
+          if depth == 0 {
+            return Some(((cursor_y, cursor_x), Some((y, x))));
+          }
+        }
+      }
+    }
+    Some(((cursor_y, cursor_x), None))
+  }
+
+  fn set_highlight(
+    &mut self,
+    (y, x): Position,
+    highlight_type: HighlightType,
+  ) -> Option<(usize, usize, HighlightType)> {
+    let row = self.editor_rows.get_editor_row_mut(y);
+    let index = row.render_indices()[x];
+    let previous = *row.highlight.get(index)?;
+    row.highlight[index] = highlight_type;
+    Some((y, index, previous))
+  }
+
+  // Returns the overwritten highlights so they can be restored after drawing
+  fn highlight_matching_bracket(&mut self) -> Vec<(usize, usize, HighlightType)> {
+    match self.matching_bracket() {
+      Some((bracket, Some(partner))) => [
+        self.set_highlight(bracket, HighlightType::MatchingBracket),
+        self.set_highlight(partner, HighlightType::MatchingBracket),
+      ].into_iter().flatten().collect(),
+      Some((bracket, None)) => self
+        .set_highlight(bracket, HighlightType::UnmatchedBracket)
+        .into_iter()
+        .collect(),
+      None => Vec::new(),
+    }
+  }
+
   pub fn clear_screen() -> crossterm::Result<()> {
-    log::log::log("INFO".to_string(), format!("Clearing screen.\n\n"));
+    log::log::log("DEBUG".to_string(), "Clearing screen.\n\n".to_string());
     execute!(io::stdout(), terminal::Clear(terminal::ClearType::All))?;
     execute!(io::stdout(), cursor::MoveTo(0, 0))
   }
 
   pub fn refresh_screen(&mut self) -> crossterm::Result<()> {
-    log::log::log("INFO".to_string(), "Refreshing screen.".to_string());
-    self.cursor_controller.scroll(&self.editor_rows);
+    log::log::log("DEBUG".to_string(), "Refreshing screen.".to_string());
+    self.editor_rows.sign_column = !self.editor_rows.marks.is_empty()
+      || self.editor_rows.row_contents.iter().any(|row| row.added || row.modified);
+    self.cursor_controller.scroll(&self.editor_rows, self.options.wrap);
     queue!(
       self.editor_contents,
       cursor::Hide,
@@ -318,86 +1531,240 @@ impl Output {
 
     self.draw_message_bar();
 
-    let cursor_x = self.cursor_controller.render_x - self.cursor_controller.column_offset;
-    let cursor_y = self.cursor_controller.cursor_y - self.cursor_controller.row_offset;
+    let (cursor_x, cursor_y) = self.cursor_controller.screen_position(&self.editor_rows, self.options.wrap);
+    let cursor_x = self.editor_rows.gutter_width() + cursor_x;
+    let cursor_y = self.pane_top(self.active_pane) + cursor_y;
 
     queue!(
       self.editor_contents,
       cursor::MoveTo(cursor_x as u16, cursor_y as u16),
+      self.cursor_style,
       cursor::Show,
     )?;
     self.editor_contents.flush()
   }
 
   pub fn draw_rows(&mut self) {
+    self.panes[self.active_pane] = self.cursor_controller;
+    // Caught up first, so the matching bracket isn't marked on a row about to be redone
+    let until = self.panes.iter().map(|pane| pane.row_offset + pane.screen_rows).max().unwrap_or_default();
+    self.highlight_stale(until);
+    let bracket_highlights = self.highlight_matching_bracket();
+    for index in 0..self.panes.len() {
+      if index > 0 {
+        self.draw_separator();
+      }
+      let mut pane = self.panes[index];
+      // Other panes may have been left past the end by edits in this one
+      if index != self.active_pane {
+        pane.clamp(&self.editor_rows);
+        pane.scroll(&self.editor_rows, self.options.wrap);
+      }
+      self.draw_pane(&pane);
+    }
+
+    for (y, index, highlight) in bracket_highlights {
+      self.editor_rows.get_editor_row_mut(y).highlight[index] = highlight;
+    }
+  }
+
+  // The line between panes, naming the file like vim's window status lines
+  fn draw_separator(&mut self) {
+    let name = Self::buffer_name(&self.editor_rows);
+    let name = text::truncate(&name, self.window_size.0);
+    let line = format!("{}{}", name, " ".repeat(self.window_size.0 - text::width(name)));
+    self.editor_contents.push_str(&style::Attribute::Reverse.to_string(), None);
+    self.editor_contents.push_str(&line, None);
+    self.editor_contents.push_str(&style::Attribute::Reset.to_string(), None);
+    self.editor_contents.push_str("\r\n", None);
+  }
+
+  fn draw_pane(&mut self, pane: &CursorController) {
     let screen_columns = self.window_size.0;
-    let screen_rows = self.window_size.1;
-
-    for i in 0..screen_rows {
-      let file_row = i + self.cursor_controller.row_offset;
-      if file_row >= self.editor_rows.number_of_rows() {
-        if self.editor_rows.number_of_rows() == 0 && i == screen_rows / 3 {
-          let mut welcome = format!("Vimrs --- Version {}\r\n", CONFIG.version);
-          if welcome.len() > screen_columns {
-            welcome.truncate(screen_columns);
+    let screen_rows = pane.screen_rows;
+    self.highlight_stale(pane.row_offset + screen_rows);
+    let number_width = self.editor_rows.number_width();
+    let screen_lines = pane.screen_lines(&self.editor_rows, self.options.wrap);
+    // The welcome text is only for an empty scratch buffer, an empty named file is just
+    // tildes, and it's left out of panes too small to hold it
+    let welcome = [
+      format!("Vimrs --- Version {}", CONFIG.version),
+      String::from("A text editor written in Rust"),
+    ];
+    let welcome_top = screen_rows / 3;
+    let show_welcome = self.editor_rows.number_of_rows() == 0
+      && self.editor_rows.filename.is_none()
+      && welcome_top + welcome.len() <= screen_rows
+      && welcome.iter().all(|line| line.len() < screen_columns);
+    let search_pattern = self.last_search.as_deref().and_then(|term| self.search_pattern(term).ok());
+
+    for (i, screen_line) in screen_lines.into_iter().enumerate() {
+      let mut line_background = None;
+      if let Some((file_row, column_offset, visible_end, first)) = screen_line {
+        let cursor_y = pane.cursor_y;
+        if self.options.cursor_line && file_row == cursor_y {
+          line_background = Some(CURSOR_LINE_COLOR);
+        }
+        set_line_background(&mut self.editor_contents, line_background);
+        let line_number = self.options.line_number(file_row, cursor_y);
+        // Marks win over the `+` on rows added and `~` on rows
+        // changed since the last write
+        if self.editor_rows.sign_column {
+          let row = self.editor_rows.get_editor_row(file_row);
+          let mark = self.editor_rows.marks
+            .iter()
+            .filter(|(_, &(y, _))| y == file_row)
+            .map(|(&name, _)| name)
+            .min();
+          let sign = match mark {
+            Some(sign) if first => sign,
+            None if first && row.added => '+',
+            None if first && row.modified => '~',
+            _ => ' ',
+          };
+          self.editor_contents.push_str(&sign.to_string(), Some("cyan".to_string()));
+          set_line_background(&mut self.editor_contents, line_background);
+        }
+        // Wrapped continuation lines leave the gutter blank, and `:set nonu` drops it
+        if number_width > 0 {
+          let gutter = if first {
+            format!("{:>width$} ", line_number, width = number_width - 1)
+          } else {
+            " ".repeat(number_width)
+          };
+          self.editor_contents.push_str(&gutter, Some(CONFIG.line_number_color.to_string()));
+          set_line_background(&mut self.editor_contents, line_background);
+        }
+        // Split the visible part of the row around any visual selection
+        let (select_start, select_end) = self
+          .selected_columns(file_row)
+          .map(|(start, end)| {
+            (start.clamp(column_offset, visible_end), end.clamp(column_offset, visible_end))
+          })
+          .unwrap_or((visible_end, visible_end));
+        let row = self.editor_rows.get_editor_row(file_row);
+        let render = &row.render;
+        // A full-width character cut by an edge of the screen is left out, with a space
+        // in its place on the left so the rest of the row stays in its columns
+        let width_to = |column: usize| text::width(&render[..row.render_byte_index(column)]);
+        let visible_end = visible_end - width_to(visible_end).saturating_sub(visible_end);
+        let text_start = width_to(column_offset).clamp(column_offset, visible_end);
+        (column_offset..text_start).for_each(|_| self.editor_contents.push(' '));
+        let column_offset = text_start;
+        let search_highlight = search_pattern
+          .as_ref()
+          .map(|pattern| highlight_matches(render, &row.highlight, pattern));
+        let highlight = search_highlight.as_deref().unwrap_or(&row.highlight);
+
+        // With `list`, tabs get a marker and trailing whitespace a background, so
+        // the row is also split where those start
+        let mut tab_columns = Vec::new();
+        let mut trailing_start = visible_end;
+        if self.options.list {
+          tab_columns = row
+            .row_content
+            .chars()
+            .enumerate()
+            .filter(|(_, c)| *c == '\t')
+            .map(|(at, _)| row.render_column(at))
+            .collect();
+          trailing_start = row.render_column(row.row_content.trim_end().chars().count());
+        }
+        // With `spell`, misspelled words are underlined
+        let misspelled: Vec<(usize, usize)> = if self.options.spell {
+          spell::misspelled(render, &row.highlight)
+            .into_iter()
+            .map(|word| (text::width(&render[..word.start]), text::width(&render[..word.end])))
+            .collect()
+        } else {
+          Vec::new()
+        };
+        let mut boundaries: Vec<usize> = [column_offset, select_start, select_end, visible_end]
+          .into_iter()
+          .chain(tab_columns.iter().flat_map(|&column| [column, column + 1]))
+          .chain([trailing_start])
+          .chain(misspelled.iter().flat_map(|&(start, end)| [start, end]))
+          .map(|column| column.clamp(column_offset, visible_end))
+          .collect();
+        boundaries.sort_unstable();
+        boundaries.dedup();
+
+        for segment in boundaries.windows(2) {
+          let (segment_start, segment_end) = (segment[0], segment[1]);
+          // Slice on character boundaries so multibyte text never gets split
+          let start = row.render_byte_index(segment_start);
+          let end = row.render_byte_index(segment_end);
+          if start == end {
+            continue;
           }
-          let mut welcome_padding = (screen_columns - welcome.len()) / 2;
-          if welcome_padding != 0 {
-            self.editor_contents.push_str("~", Some(CONFIG.tilde_color.to_string()));
-            welcome_padding -= 1;
+          let selected = (select_start..select_end).contains(&segment_start);
+          let trailing = segment_start >= trailing_start;
+          let misspelled = misspelled.iter().any(|&(start, end)| (start..end).contains(&segment_start));
+          if selected {
+            self.editor_contents.push_str(&style::Attribute::Reverse.to_string(), None);
           }
-          (0..welcome_padding).for_each(|_| self.editor_contents.push(' '));
-          self.editor_contents.push_str(&welcome, None);
-
-          let mut description = String::from("A text editor written in Rust\r\n");
-          if description.len() > screen_columns {
-            description.truncate(screen_columns);
+          if misspelled {
+            let _ = queue!(self.editor_contents, style::SetAttribute(style::Attribute::Underlined));
           }
-          let mut description_padding = (screen_columns - description.len()) / 2;
-          if description_padding != 0 {
-            self.editor_contents.push_str("~", Some(CONFIG.tilde_color.to_string()));
-            description_padding -= 1;
+          if trailing {
+            let _ = queue!(self.editor_contents, style::SetBackgroundColor(style::Color::DarkRed));
           }
-          (0..description_padding).for_each(|_| self.editor_contents.push(' '));
-          self.editor_contents.push_str(&description, None);
-          self.editor_contents.push_str("~", Some(CONFIG.tilde_color.to_string()));
-        } else {
-          // TODO- Figure out the best way to handle this
-          // Should the push_str function signature be changed to accept a color string
-          // Or should the color be set before the push call
-          // execute!(
-          //   io::stdout(),
-          //   style::SetForegroundColor(style::Color::Magenta),
-          // );
-          // self.editor_contents.push('~');
-          // execute!(io::stdout(), style::ResetColor);
+          if tab_columns.contains(&segment_start) {
+            let _ = queue!(self.editor_contents, style::SetForegroundColor(style::Color::DarkGrey));
+            self.editor_contents.push('\u{203a}');
+            let _ = queue!(self.editor_contents, style::SetForegroundColor(style::Color::White));
+          } else {
+            self.syntax_highlight
+              .as_ref()
+              .map(|syntax_highlight| {
+                syntax_highlight.color_row(
+                  &render[start..end],
+                  &highlight[start..end],
+                  &mut self.editor_contents,
+                )
+              })
+              .unwrap_or_else(|| self.editor_contents.push_str(&render[start..end], None));
+          }
+          if trailing {
+            let _ = queue!(self.editor_contents, style::SetBackgroundColor(style::Color::Reset));
+          }
+          set_line_background(&mut self.editor_contents, line_background);
+          if misspelled {
+            let _ = queue!(self.editor_contents, style::SetAttribute(style::Attribute::NoUnderline));
+          }
+          if selected {
+            self.editor_contents.push_str(&style::Attribute::NoReverse.to_string(), None);
+          }
+        }
+      } else if show_welcome && (welcome_top..welcome_top + welcome.len()).contains(&i) {
+        let line = &welcome[i - welcome_top];
+        let mut padding = (screen_columns - line.len()) / 2;
+        if padding != 0 {
           self.editor_contents.push_str("~", Some(CONFIG.tilde_color.to_string()));
+          padding -= 1;
         }
+        (0..padding).for_each(|_| self.editor_contents.push(' '));
+        self.editor_contents.push_str(line, None);
       } else {
-        let line_number = (file_row + 1) as u32;
-        self.editor_contents.push_str(format!("{:>3} ", line_number).as_str(), Some(CONFIG.line_number_color.to_string()));
-        let row = self.editor_rows.get_editor_row(file_row);
-        let render = &row.render;
-        let column_offset = self.cursor_controller.column_offset;
-        let len = cmp::min(render.len().saturating_sub(column_offset), screen_columns);
-        let start = if len == 0 { 0 } else { column_offset };
-
-        self.syntax_highlight
-          .as_ref()
-          .map(|syntax_highlight| {
-            syntax_highlight.color_row(
-              &render[start..start + len],
-              &row.highlight[start..start + len],
-              &mut self.editor_contents,
-            )
-          })
-          .unwrap_or_else(|| self.editor_contents.push_str(&render[start..start + len], None));
-
+        // TODO- Figure out the best way to handle this
+        // Should the push_str function signature be changed to accept a color string
+        // Or should the color be set before the push call
+        // execute!(
+        //   io::stdout(),
+        //   style::SetForegroundColor(style::Color::Magenta),
+        // );
+        // self.editor_contents.push('~');
+        // execute!(io::stdout(), style::ResetColor);
+        self.editor_contents.push_str("~", Some(CONFIG.tilde_color.to_string()));
       }
+      // The clear fills the rest of the line with the current background
       queue!(
         self.editor_contents,
         terminal::Clear(terminal::ClearType::UntilNewLine),
       ).unwrap();
+      if line_background.is_some() {
+        let _ = queue!(self.editor_contents, style::SetBackgroundColor(style::Color::Reset));
+      }
 
       self.editor_contents.push_str("\r\n", None);
     }
@@ -407,51 +1774,147 @@ impl Output {
     self.cursor_controller.move_cursor(direction, &self.editor_rows);
   }
 
+  pub fn find_in_line(&mut self, kind: char, target: char, count: usize, repeat: bool) {
+    if !self.cursor_controller.find_in_line(kind, target, count, repeat, &self.editor_rows) {
+      self.status_message.set_message(format!("Can't find {:?}", target));
+    }
+  }
+
+  pub fn click(&mut self, column: u16, row: u16) {
+    // Only clicks inside the active pane move the cursor
+    let Some(row) = (row as usize).checked_sub(self.pane_top(self.active_pane)) else {
+      return;
+    };
+    self.cursor_controller.click(column as usize, row, &self.editor_rows, self.options.wrap);
+  }
+
+  // Screen row where a pane starts, below the panes and separators above it
+  fn pane_top(&self, index: usize) -> usize {
+    self.panes[..index].iter().map(|pane| pane.screen_rows + 1).sum()
+  }
+
+  // Shares the text rows between the panes, each after the first giving one up to the
+  // separator above it
+  fn layout_panes(&mut self) {
+    self.panes[self.active_pane] = self.cursor_controller;
+    let count = self.panes.len();
+    let rows = self.window_size.1.saturating_sub(count - 1);
+    for (index, pane) in self.panes.iter_mut().enumerate() {
+      pane.screen_columns = self.window_size.0;
+      pane.screen_rows = cmp::max(rows / count + usize::from(index < rows % count), 1);
+    }
+    self.cursor_controller = self.panes[self.active_pane];
+  }
+
+  // `:sp`, a second view of the buffer above the current one. Every pane needs a row
+  // of text, and each after the first a separator line above it
+  pub fn split(&mut self) {
+    let count = self.panes.len() + 1;
+    if count * 2 - 1 > self.window_size.1 {
+      self.status_message.set_message("E36: Not enough room".to_string());
+      return;
+    }
+    self.panes[self.active_pane] = self.cursor_controller;
+    self.panes.insert(self.active_pane, self.cursor_controller);
+    self.layout_panes();
+  }
+
+  // Closes the active pane, returning false when it's the only one
+  pub fn close_pane(&mut self) -> bool {
+    if self.panes.len() == 1 {
+      return false;
+    }
+    self.panes.remove(self.active_pane);
+    self.active_pane = cmp::min(self.active_pane, self.panes.len() - 1);
+    self.cursor_controller = self.panes[self.active_pane];
+    self.cursor_controller.clamp(&self.editor_rows);
+    self.layout_panes();
+    true
+  }
+
+  // Ctrl-W moves between panes, wrapping around
+  pub fn cycle_panes(&mut self, offset: isize) {
+    self.panes[self.active_pane] = self.cursor_controller;
+    self.active_pane = (self.active_pane as isize + offset).rem_euclid(self.panes.len() as isize) as usize;
+    self.cursor_controller = self.panes[self.active_pane];
+    self.cursor_controller.clamp(&self.editor_rows);
+    self.selection_anchor = None;
+  }
+
+  pub fn scroll_half_page(&mut self, down: bool) {
+    self.cursor_controller.scroll_half_page(down, &self.editor_rows);
+  }
+
+  pub fn scroll_lines(&mut self, lines: isize) {
+    self.cursor_controller.scroll_lines(lines, &self.editor_rows);
+  }
+
+  // Where the view is in the file like vim shows it: `Top`, `Bot`, `All` or how far through
+  fn scroll_position(&self) -> String {
+    let number_of_rows = self.editor_rows.number_of_rows();
+    let top = self.cursor_controller.row_offset == 0;
+    let bottom = self.cursor_controller
+      .screen_lines(&self.editor_rows, self.options.wrap)
+      .iter()
+      .any(|line| line.is_none_or(|(file_row, _, _, _)| file_row + 1 >= number_of_rows));
+    match (top, bottom) {
+      (true, true) => "All".to_string(),
+      (true, false) => "Top".to_string(),
+      (false, true) => "Bot".to_string(),
+      _ => format!("{}%", (self.cursor_controller.cursor_y + 1) * 100 / number_of_rows),
+    }
+  }
+
   pub fn draw_status_bar(&mut self) {
     // Invert color
     self.editor_contents
       .push_str(&style::Attribute::Reverse.to_string(), None);
 
-    let info = format!(
-      // Name, number of lines, size in bytes
-      "\"{}\" {} Lines, {:?}B written    {}",
-      self.editor_rows
-        .filename
-        .as_ref()
-        .and_then(|path| path.file_name())
-        .and_then(|filename| filename.to_str())
-        .unwrap_or("[Untitled]"),
+    let filename = self.editor_rows
+      .filename
+      .as_ref()
+      .and_then(|path| path.file_name())
+      .and_then(|filename| filename.to_str())
+      .unwrap_or("[Untitled]");
+    let details = format!(
+      // Number of lines, size in bytes
+      "{} {} Lines, {:?}B written    {}",
+      if self.options.readonly { " [RO]" } else { "" },
       self.editor_rows.number_of_rows(),
-      match self.editor_rows.file_size {
-        Some(size) => size,
-        _ => 0,
-      },
+      self.editor_rows.file_size.unwrap_or_default(),
       if self.dirty { "(modified)" } else { "" },
     );
 
-    let info_length = cmp::min(info.len(), self.window_size.0);
-
     let line_info = format!(
-      "{}, Ln {}, Col {}",
+      "{}, Ln {}, Col {}  {}",
       self.syntax_highlight
         .as_ref()
         .map(|highlight| highlight.file_type())
         .unwrap_or("no ft"),
       self.cursor_controller.cursor_y + 1,
       self.cursor_controller.cursor_x + 1,
+      self.scroll_position(),
     );
 
-    self.editor_contents.push_str(&info[..info_length], None);
+    // The line info keeps its place on the right, the name gives way to fit the rest
+    let screen_columns = self.window_size.0;
+    let line_info = text::truncate(&line_info, screen_columns);
+    let line_info_length = text::width(line_info);
+    let info_columns = screen_columns.saturating_sub(line_info_length + 1);
+    let name_columns = info_columns.saturating_sub(text::width(&details) + 2);
+    let filename = if text::width(filename) > name_columns {
+      format!("{}\u{2026}", text::truncate(filename, name_columns.saturating_sub(1)))
+    } else {
+      filename.to_string()
+    };
+    let info = format!("\"{}\"{}", filename, details);
+    let info = text::truncate(&info, info_columns);
+    let padding = screen_columns.saturating_sub(text::width(info) + line_info_length);
+
+    self.editor_contents.push_str(info, None);
+    (0..padding).for_each(|_| self.editor_contents.push(' '));
+    self.editor_contents.push_str(line_info, None);
 
-    for i in info_length..self.window_size.0 {
-      if self.window_size.0 - i == line_info.len() {
-        self.editor_contents.push_str(&line_info, None);
-        break;
-      } else {
-        self.editor_contents.push(' ');
-      }
-    }
-        
     // Reset color
     self.editor_contents
       .push_str(&style::Attribute::Reset.to_string(), None);
@@ -465,9 +1928,24 @@ impl Output {
       terminal::Clear(terminal::ClearType::UntilNewLine),
     ).unwrap();
 
-    if let Some(msg) = self.status_message.message() {
-      self.editor_contents
-        .push_str(&msg[..cmp::min(self.window_size.0, msg.len())], None);
+    let screen_columns = self.window_size.0;
+    let indicator = text::truncate(&self.status_message.indicator, screen_columns).to_string();
+    let indicator_width = text::width(&indicator);
+    // The message gives way to the indicator, keeping a space between them
+    let room = match indicator_width {
+      0 => screen_columns,
+      width => screen_columns.saturating_sub(width + 1),
+    };
+    let message = self.status_message
+      .message()
+      .map(|msg| text::truncate(msg, room).to_string())
+      .unwrap_or_default();
+    let message_width = text::width(&message);
+    self.editor_contents.push_str(&message, None);
+    if indicator_width > 0 {
+      let padding = screen_columns - indicator_width - message_width;
+      self.editor_contents.push_str(&" ".repeat(padding), None);
+      self.editor_contents.push_str(&indicator, None);
     }
   }
 }
@@ -480,8 +1958,7 @@ enum SearchDirection {
 struct SearchIndex {
   x_index: usize,
   y_index: usize,
-  x_direction: Option<SearchDirection>,
-  y_direction: Option<SearchDirection>,
+  wrapped: Option<&'static str>,
   previous_highlight: Option<(usize, Vec<HighlightType>)>,
 }
 
@@ -490,8 +1967,7 @@ impl SearchIndex {
     Self {
       x_index: 0,
       y_index: 0,
-      x_direction: None,
-      y_direction: None,
+      wrapped: None,
       previous_highlight: None,
     }
   }
@@ -499,8 +1975,111 @@ impl SearchIndex {
   fn reset(&mut self) {
     self.x_index = 0;
     self.y_index = 0;
-    self.x_direction = None;
-    self.y_direction = None;
+    self.wrapped = None;
     self.previous_highlight = None;
   }
-}
\ No newline at end of file
+}
+
+// Colors set with `push_str` end in a full reset, so the cursor line's background
+// is set again after each of them
+fn set_line_background(editor_contents: &mut EditorContents, background: Option<style::Color>) {
+  if let Some(color) = background {
+    let _ = queue!(editor_contents, style::SetBackgroundColor(color));
+  }
+}
+
+// The comment leader at the start of `line`: `comment_start`, any more marks like the
+// third `/` of `///` or the `!` of `//!`, and the space after them
+fn comment_leader<'a>(line: &'a str, comment_start: &str) -> Option<&'a str> {
+  if comment_start.is_empty() || !line.starts_with(comment_start) {
+    return None;
+  }
+  let rest = &line[comment_start.len()..];
+  let marks = rest.len() - rest.trim_start_matches(|c| comment_start.contains(c) || c == '!').len();
+  let end = comment_start.len() + marks;
+  Some(&line[..end + usize::from(line[end..].starts_with(' '))])
+}
+
+// A copy of a row's highlight with every match of the last search marked
+fn highlight_matches(render: &str, highlight: &[HighlightType], pattern: &search::Pattern) -> Vec<HighlightType> {
+  let mut highlight = highlight.to_vec();
+  // Rows of a buffer without a highlighter have nothing to mark
+  if highlight.len() != render.len() {
+    return highlight;
+  }
+  for range in pattern.matches(render) {
+    highlight[range].fill(HighlightType::SearchMatch);
+  }
+  highlight
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::ScriptedInput;
+
+  fn output() -> Output {
+    Output::with_io(Box::new(ScriptedInput::new(Vec::new())), Box::new(io::sink()), 80, 24)
+  }
+
+  #[test]
+  fn split_refuses_panes_without_room() {
+    // 24 rows leave 22 for text, enough for 11 panes and the separators between them
+    let mut output = output();
+    (0..20).for_each(|_| output.split());
+    assert_eq!(output.panes.len(), 11);
+    let rows: usize = output.panes.iter().map(|pane| pane.screen_rows).sum();
+    assert_eq!(rows + output.panes.len() - 1, 22);
+    assert!(output.panes.iter().all(|pane| pane.screen_rows >= 1));
+  }
+
+  #[test]
+  fn sign_column_shows_for_added_rows_and_marks() {
+    let (mut added, mut marked) = (output(), output());
+    added.refresh_screen().unwrap();
+    assert!(!added.editor_rows.sign_column);
+    added.editor_rows.insert_row(0, "new".to_string());
+    added.refresh_screen().unwrap();
+    assert!(added.editor_rows.sign_column);
+
+    marked.set_mark('a');
+    marked.refresh_screen().unwrap();
+    assert!(marked.editor_rows.sign_column);
+  }
+
+  #[test]
+  fn relative_numbers_keep_the_gutter_without_number() {
+    let mut output = output();
+    output.set_option("rnu").unwrap();
+    assert_eq!(output.options.line_number(4, 6), 2);
+    assert_eq!(output.options.line_number(6, 6), 7);
+    output.set_option("nonu").unwrap();
+    assert!(output.editor_rows.number_width() > 0);
+    assert_eq!(output.options.line_number(4, 6), 2);
+    assert_eq!(output.options.line_number(6, 6), 0);
+    output.set_option("nornu").unwrap();
+    assert_eq!(output.editor_rows.number_width(), 0);
+  }
+
+  #[test]
+  fn rows_below_the_screen_are_highlighted_once_scrolled_into_view() {
+    // 24 rows leave 22 for text
+    let mut output = output();
+    output.syntax_highlight = Some(Box::new(RustHighlight::new()));
+    output.editor_rows = EditorRows::from_str(&"let x = 1;\n".repeat(100), &output.syntax_highlight);
+    output.insert_character('/');
+    output.insert_character('*');
+    output.refresh_screen().unwrap();
+    let rows = &output.editor_rows.row_contents;
+    assert!(rows[..22].iter().all(|row| row.is_comment));
+    assert!(rows[22].stale);
+    assert!(rows[22..].iter().all(|row| !row.is_comment));
+
+    output.cursor_controller.cursor_y = 60;
+    output.refresh_screen().unwrap();
+    let rows = &output.editor_rows.row_contents;
+    assert!(rows[..61].iter().all(|row| row.is_comment && !row.stale));
+    assert!(rows[61].stale);
+    assert!(rows[61..].iter().all(|row| !row.is_comment));
+  }
+}
@@ -0,0 +1,96 @@
+// Finding a search term in a row, as plain text or a regex, with or without regard to case
+use std::iter;
+use std::ops::Range;
+use regex::{Regex, RegexBuilder};
+
+// A search term, taken literally unless `:set regex` is on
+pub enum Pattern {
+  Literal { term: String, ignore_case: bool },
+  Regex(Regex),
+}
+
+impl Pattern {
+  pub fn new(term: &str, ignore_case: bool, regex: bool) -> Result<Self, String> {
+    if !regex {
+      return Ok(Pattern::Literal { term: term.to_string(), ignore_case });
+    }
+    RegexBuilder::new(term)
+      .case_insensitive(ignore_case)
+      .build()
+      .map(Pattern::Regex)
+      // Parse errors point at the term over several lines, the last one says what's wrong
+      .map_err(|error| {
+        let error = error.to_string();
+        let reason = error.lines().last().unwrap_or_default().trim_start_matches("error: ");
+        format!("Invalid regex: {}", reason)
+      })
+  }
+
+  pub fn matches(&self, text: &str) -> Vec<Range<usize>> {
+    match self {
+      Pattern::Literal { term, ignore_case } => matches(text, term, *ignore_case),
+      Pattern::Regex(regex) => regex.find_iter(text).map(|found| found.range()).collect(),
+    }
+  }
+}
+
+// Byte ranges of every match of `term` in `text`, overlapping ones included. Ignoring
+// case compares lowercased text, whose chars can take more or fewer bytes than the
+// originals, so the ranges are mapped back onto whole chars of `text`
+pub fn matches(text: &str, term: &str, ignore_case: bool) -> Vec<Range<usize>> {
+  if term.is_empty() {
+    return Vec::new();
+  }
+  if !ignore_case {
+    return find_all(text, term);
+  }
+  // Where in `text` each byte of the lowercased text came from
+  let mut original = Vec::with_capacity(text.len());
+  let mut lowered = String::with_capacity(text.len());
+  for (index, c) in text.char_indices() {
+    for lower in c.to_lowercase() {
+      lowered.push(lower);
+      original.extend(iter::repeat_n(index, lower.len_utf8()));
+    }
+  }
+  find_all(&lowered, &term.to_lowercase())
+    .into_iter()
+    .map(|range| {
+      let last = original[range.end - 1];
+      original[range.start]..last + text[last..].chars().next().map_or(0, char::len_utf8)
+    })
+    .collect()
+}
+
+fn find_all(text: &str, term: &str) -> Vec<Range<usize>> {
+  let mut ranges = Vec::new();
+  let mut from = 0;
+  while let Some(at) = text[from..].find(term) {
+    let start = from + at;
+    ranges.push(start..start + term.len());
+    from = start + text[start..].chars().next().map_or(1, char::len_utf8);
+  }
+  ranges
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn takes_special_characters_literally_without_regex() {
+    let pattern = Pattern::new("a.b(", false, false).unwrap();
+    assert_eq!(pattern.matches("a.b( axb( A.B("), vec![0..4]);
+  }
+
+  #[test]
+  fn ignores_case_in_a_regex() {
+    let pattern = Pattern::new("fo+", true, true).unwrap();
+    assert_eq!(pattern.matches("FOO f fOo"), [0..3, 6..9]);
+  }
+
+  #[test]
+  fn explains_an_invalid_regex() {
+    assert_eq!(Pattern::new("(a", false, true).err(), Some("Invalid regex: unclosed group".to_string()));
+  }
+}
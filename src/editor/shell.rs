@@ -0,0 +1,38 @@
+// Runs shell commands for `:w !command` and `:%!command`, feeding them text on stdin
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::thread;
+
+use crate::log;
+
+// The command's stdout, or what it said on stderr (or its exit status) when it fails
+pub fn run(command: &str, input: &str) -> Result<String, String> {
+  log::log::log("INFO".to_string(), format!("Running {:?}", command));
+  let mut child = Command::new("sh")
+    .arg("-c")
+    .arg(command)
+    .stdin(Stdio::piped())
+    .stdout(Stdio::piped())
+    .stderr(Stdio::piped())
+    .spawn()
+    .map_err(|error| format!("Cannot run {:?}: {}", command, error))?;
+  // Written from another thread so a command that fills its stdout before reading
+  // all of stdin can't leave both sides waiting
+  let mut stdin = child.stdin.take().expect("stdin is piped");
+  let input = input.to_string();
+  let writer = thread::spawn(move || stdin.write_all(input.as_bytes()));
+  let output = child
+    .wait_with_output()
+    .map_err(|error| format!("Cannot run {:?}: {}", command, error))?;
+  // A command that exits without reading everything closes the pipe early, which is fine
+  let _ = writer.join();
+  if !output.status.success() {
+    // The first line is usually the one that says what went wrong
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    return Err(match stderr.lines().map(str::trim).find(|line| !line.is_empty()) {
+      Some(line) => line.to_string(),
+      None => format!("{:?} failed: {}", command, output.status),
+    });
+  }
+  String::from_utf8(output.stdout).map_err(|_| format!("{:?} did not print UTF-8 text", command))
+}
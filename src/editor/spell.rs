@@ -0,0 +1,57 @@
+// `:set spell`, underlining words missing from a plain word list, one word per line.
+// The list is `spell_file` from the config, or else the system's
+use std::collections::HashSet;
+use std::fs;
+use std::ops::Range;
+use std::sync::LazyLock;
+
+use crate::{log, CONFIG};
+use super::highlight::HighlightType;
+
+const SYSTEM_WORD_LISTS: [&str; 2] = ["/usr/share/dict/words", "/usr/dict/words"];
+
+// Lowercased, empty when no list could be read
+static WORDS: LazyLock<HashSet<String>> = LazyLock::new(|| {
+  let files = CONFIG.spell_file.as_deref().into_iter().chain(SYSTEM_WORD_LISTS);
+  let Some(contents) = files.into_iter().find_map(|file| fs::read_to_string(file).ok()) else {
+    log::log::log("WARN".to_string(), "No word list found for spell checking".to_string());
+    return HashSet::new();
+  };
+  contents
+    .lines()
+    .map(|word| word.trim().to_lowercase())
+    .filter(|word| !word.is_empty())
+    .collect()
+});
+
+pub fn available() -> bool {
+  !WORDS.is_empty()
+}
+
+// Byte ranges of the misspelled words in a rendered row. Only plain text is checked,
+// so strings, comments, keywords and code are left alone
+pub fn misspelled(render: &str, highlight: &[HighlightType]) -> Vec<Range<usize>> {
+  let mut ranges = Vec::new();
+  let mut start = None;
+  // A trailing separator closes the last word
+  for (index, c) in render.char_indices().chain([(render.len(), ' ')]) {
+    // Apostrophes stay inside words like "don't"
+    let in_word = c.is_alphabetic() || (c == '\'' && start.is_some());
+    match (start, in_word) {
+      (None, true) => start = Some(index),
+      (Some(word_start), false) => {
+        start = None;
+        let word = render[word_start..index].trim_end_matches('\'');
+        let end = word_start + word.len();
+        let plain = highlight
+          .get(word_start..end)
+          .is_none_or(|highlight| highlight.iter().all(|highlight| matches!(highlight, HighlightType::Normal)));
+        if plain && !WORDS.contains(&word.to_lowercase()) {
+          ranges.push(word_start..end);
+        }
+      },
+      _ => {},
+    }
+  }
+  ranges
+}
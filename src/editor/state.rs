@@ -0,0 +1,54 @@
+// Remembers where the cursor was in each file, like vim's viminfo
+use std::{env, fs};
+use std::path::{Path, PathBuf};
+
+use crate::log;
+
+// Oldest entries are dropped once there are more than this
+const MAX_ENTRIES: usize = 100;
+
+fn state_file() -> Option<PathBuf> {
+  env::var_os("HOME").map(|home| PathBuf::from(home).join(".vimrs_state"))
+}
+
+// One `cursor_y cursor_x path` line per file, most recent first
+fn entries() -> Vec<(usize, usize, PathBuf)> {
+  let Some(contents) = state_file().and_then(|file| fs::read_to_string(file).ok()) else {
+    return Vec::new();
+  };
+  contents
+    .lines()
+    .filter_map(|line| {
+      let mut fields = line.splitn(3, '\t');
+      let y = fields.next()?.parse().ok()?;
+      let x = fields.next()?.parse().ok()?;
+      Some((y, x, PathBuf::from(fields.next()?)))
+    })
+    .collect()
+}
+
+pub fn cursor_position(file: &Path) -> Option<(usize, usize)> {
+  let file = fs::canonicalize(file).ok()?;
+  entries()
+    .into_iter()
+    .find(|(_, _, path)| *path == file)
+    .map(|(y, x, _)| (y, x))
+}
+
+pub fn save_cursor_position(file: &Path, (y, x): (usize, usize)) {
+  let (Some(state_file), Ok(file)) = (state_file(), fs::canonicalize(file)) else {
+    return;
+  };
+  let mut entries = entries();
+  entries.retain(|(_, _, path)| *path != file);
+  entries.insert(0, (y, x, file));
+  entries.truncate(MAX_ENTRIES);
+
+  let contents: String = entries
+    .iter()
+    .map(|(y, x, path)| format!("{}\t{}\t{}\n", y, x, path.display()))
+    .collect();
+  if let Err(error) = fs::write(state_file, contents) {
+    log::log::log("WARN".to_string(), format!("Failed to save the cursor position: {}", error));
+  }
+}
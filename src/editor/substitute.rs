@@ -0,0 +1,116 @@
+use std::ops::Range;
+use super::search::Pattern;
+
+// Parses and applies `s/pattern/replacement/flags` commands
+pub struct Substitution {
+  pub pattern: String,
+  pub replacement: String,
+  pub global: bool,
+  pub confirm: bool, // Asks before each replacement
+}
+
+impl Substitution {
+  // `command` is everything after the `s`, starting with the delimiter
+  pub fn parse(command: &str) -> Result<Self, String> {
+    let mut characters = command.chars();
+    let delimiter = characters.next().ok_or("Missing delimiter.")?;
+
+    let mut fields = vec![String::new()];
+    while let Some(c) = characters.next() {
+      match c {
+        '\\' => match characters.next() {
+          // An escaped delimiter or backslash stands for itself
+          Some(escaped) if escaped == delimiter || escaped == '\\' => {
+            fields.last_mut().unwrap().push(escaped)
+          },
+          Some(escaped) => {
+            fields.last_mut().unwrap().push('\\');
+            fields.last_mut().unwrap().push(escaped);
+          },
+          None => fields.last_mut().unwrap().push('\\'),
+        },
+        c if c == delimiter && fields.len() < 3 => fields.push(String::new()),
+        c => fields.last_mut().unwrap().push(c),
+      }
+    }
+
+    let mut fields = fields.into_iter();
+    let pattern = fields.next().unwrap_or_default();
+    let replacement = fields.next().unwrap_or_default();
+    let flags = fields.next().unwrap_or_default();
+    if pattern.is_empty() {
+      return Err("Empty search pattern.".to_string());
+    }
+    if let Some(flag) = flags.chars().find(|flag| !['g', 'c'].contains(flag)) {
+      return Err(format!("Unknown flag: {}", flag));
+    }
+    Ok(Self {
+      pattern,
+      replacement,
+      global: flags.contains('g'),
+      confirm: flags.contains('c'),
+    })
+  }
+
+  // Byte range of the first match starting at or after `from`. Matching the whole line
+  // keeps anchors like `^` tied to its start
+  pub fn find(&self, pattern: &Pattern, line: &str, from: usize) -> Option<Range<usize>> {
+    pattern.matches(line).into_iter().find(|range| range.start >= from)
+  }
+
+  // Replaces the match covering `range`
+  pub fn replace_at(&self, line: &str, range: Range<usize>) -> String {
+    format!("{}{}{}", &line[..range.start], self.replacement, &line[range.end..])
+  }
+
+  // Returns the new line and how many replacements were made. Literal matches can
+  // overlap, only the first of each run is replaced
+  pub fn apply(&self, pattern: &Pattern, line: &str) -> (String, usize) {
+    let mut content = String::with_capacity(line.len());
+    let mut end = 0;
+    let mut replacements = 0;
+    for range in pattern.matches(line) {
+      if range.start < end {
+        continue;
+      }
+      content.push_str(&line[end..range.start]);
+      content.push_str(&self.replacement);
+      end = range.end;
+      replacements += 1;
+      if !self.global {
+        break;
+      }
+    }
+    content.push_str(&line[end..]);
+    (content, replacements)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn replaces_the_first_or_every_match() {
+    let pattern = Pattern::new("o", false, false).unwrap();
+    let once = Substitution::parse("/o/0/").unwrap();
+    assert_eq!(once.apply(&pattern, "foo boo"), ("f0o boo".to_string(), 1));
+    let global = Substitution::parse("/o/0/g").unwrap();
+    assert_eq!(global.apply(&pattern, "foo boo"), ("f00 b00".to_string(), 4));
+  }
+
+  #[test]
+  fn replaces_overlapping_literal_matches_once_each() {
+    let pattern = Pattern::new("aa", false, false).unwrap();
+    let substitution = Substitution::parse("/aa/b/g").unwrap();
+    assert_eq!(substitution.apply(&pattern, "aaaaa"), ("bba".to_string(), 2));
+  }
+
+  #[test]
+  fn replaces_regex_matches_ignoring_case() {
+    let pattern = Pattern::new("^fo+", true, true).unwrap();
+    let substitution = Substitution::parse("/^fo+/x/g").unwrap();
+    assert_eq!(substitution.apply(&pattern, "FOO foo"), ("x foo".to_string(), 1));
+    assert_eq!(substitution.find(&pattern, "FOO foo", 1), None);
+  }
+}
@@ -0,0 +1,38 @@
+// Unsaved changes are flushed to `.filename.swp` beside the file, so a crash loses little
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::log;
+
+fn swap_file(file: &Path) -> PathBuf {
+  let name = file.file_name().map(|name| name.to_string_lossy()).unwrap_or_default();
+  file.with_file_name(format!(".{}.swp", name))
+}
+
+pub fn write(file: &Path, contents: &str) {
+  let swap_file = swap_file(file);
+  match fs::write(&swap_file, contents) {
+    Ok(()) => log::log::log("INFO".to_string(), format!("Wrote swap file {}", swap_file.display())),
+    Err(error) => log::log::log("WARN".to_string(), format!("Failed to write the swap file: {}", error)),
+  }
+}
+
+pub fn remove(file: &Path) {
+  let swap_file = swap_file(file);
+  if swap_file.exists() {
+    if let Err(error) = fs::remove_file(&swap_file) {
+      log::log::log("WARN".to_string(), format!("Failed to remove the swap file: {}", error));
+    }
+  }
+}
+
+// The swap file's contents, when it was written after the file itself
+pub fn recoverable(file: &Path) -> Option<String> {
+  let modified = |path: &Path| fs::metadata(path).and_then(|metadata| metadata.modified()).ok();
+  let swap_file = swap_file(file);
+  let swap_modified = modified(&swap_file)?;
+  if modified(file).is_some_and(|file_modified| file_modified > swap_modified) {
+    return None;
+  }
+  fs::read_to_string(swap_file).ok()
+}
@@ -2,7 +2,7 @@ use crossterm::style;
 use std::cmp;
 
 use crate::syntax_struct;
-use super::{editor::Row, highlight::{SyntaxHighlight, HighlightType}};
+use super::{editor::Row, theme, highlight::{SyntaxHighlight, HighlightType, FormatType, highlight_line_keys, number_length}};
 
 syntax_struct! {
   struct RustHighlight {
@@ -29,7 +29,13 @@ syntax_struct! {
       HighlightType::DoubleQuoteString => style::Color::Green,
       HighlightType::SingleQuoteString => style::Color::Yellow,
       HighlightType::Comment => style::Color::DarkGrey,
-      HighlightType::MultilineComment => style::Color::DarkGrey
+      HighlightType::MultilineComment => style::Color::DarkGrey,
+      HighlightType::MatchingBracket => style::Color::Magenta,
+      HighlightType::UnmatchedBracket => style::Color::DarkRed
+    },
+    formats: {
+      HighlightType::Comment | HighlightType::MultilineComment => FormatType::Italic,
+      HighlightType::Other(_) => FormatType::Bold
     }
   }
 }
@@ -48,7 +54,9 @@ syntax_struct! {
       HighlightType::DoubleQuoteString => style::Color::Red,
       HighlightType::SingleQuoteString => style::Color::Yellow,
       HighlightType::Comment => style::Color::DarkGrey,
-      HighlightType::MultilineComment => style::Color::DarkGrey
+      HighlightType::MultilineComment => style::Color::DarkGrey,
+      HighlightType::MatchingBracket => style::Color::Magenta,
+      HighlightType::UnmatchedBracket => style::Color::DarkRed
     }
   }
 }
@@ -81,14 +89,21 @@ syntax_struct! {
       HighlightType::DoubleQuoteString => style::Color::Red,
       HighlightType::SingleQuoteString => style::Color::Yellow,
       HighlightType::Comment => style::Color::DarkGrey,
-      HighlightType::MultilineComment => style::Color::DarkGrey
+      HighlightType::MultilineComment => style::Color::DarkGrey,
+      HighlightType::MatchingBracket => style::Color::Magenta,
+      HighlightType::UnmatchedBracket => style::Color::DarkRed
+    },
+    formats: {
+      HighlightType::Comment | HighlightType::MultilineComment => FormatType::Italic,
+      HighlightType::Other(_) => FormatType::Bold
     }
   }
 }
 
 syntax_struct! {
   struct ShellScriptHighlight {
-    extensions: ["sh"],
+    extensions: ["sh", "bash", "zsh"],
+    filenames: [".bashrc", ".bash_profile", ".profile", ".zshrc", "PKGBUILD"],
     file_type: "Shell",
     comment_start: "#",
     keywords: {
@@ -111,7 +126,589 @@ syntax_struct! {
       HighlightType::DoubleQuoteString => style::Color::Magenta,
       HighlightType::SingleQuoteString => style::Color::DarkYellow,
       HighlightType::Comment => style::Color::DarkGrey,
-      HighlightType::MultilineComment => style::Color::DarkGrey
+      HighlightType::MultilineComment => style::Color::DarkGrey,
+      HighlightType::MatchingBracket => style::Color::Magenta,
+      HighlightType::UnmatchedBracket => style::Color::DarkRed
+    },
+    formats: {
+      HighlightType::Comment | HighlightType::MultilineComment => FormatType::Italic,
+      HighlightType::Other(_) => FormatType::Bold
+    }
+  }
+}
+syntax_struct! {
+  struct PythonHighlight {
+    extensions: ["py"],
+    file_type: "Python",
+    comment_start: "#",
+    keywords: {
+      [style::Color::Yellow;
+        "def", "class", "import", "from", "as", "return", "if", "elif", "else", "for",
+        "while", "with", "lambda", "None", "True", "False", "pass", "break", "continue",
+        "try", "except", "finally", "raise", "yield", "global", "nonlocal", "assert",
+        "del", "in", "is", "not", "and", "or", "async", "await"
+      ],
+      [style::Color::Reset;
+        "int", "float", "str", "bool", "list", "dict", "tuple", "set", "bytes", "self"
+      ]
+    },
+    // Docstrings span lines, so treat triple quotes like a block comment
+    multiline_comment: Some(("\"\"\"", "\"\"\"")),
+    colors: {
+      HighlightType::Normal => style::Color::Reset,
+      HighlightType::Number => style::Color::Cyan,
+      HighlightType::SearchMatch => style::Color::Blue,
+      HighlightType::DoubleQuoteString => style::Color::Green,
+      HighlightType::SingleQuoteString => style::Color::Green,
+      HighlightType::Comment => style::Color::DarkGrey,
+      HighlightType::MultilineComment => style::Color::DarkGreen,
+      HighlightType::MatchingBracket => style::Color::Magenta,
+      HighlightType::UnmatchedBracket => style::Color::DarkRed
+    },
+    formats: {
+      HighlightType::Comment | HighlightType::MultilineComment => FormatType::Italic,
+      HighlightType::Other(_) => FormatType::Bold
+    }
+  }
+}
+
+syntax_struct! {
+  struct JsonHighlight {
+    extensions: ["json"],
+    filenames: [".babelrc", ".eslintrc", ".prettierrc"],
+    file_type: "JSON",
+    comment_start: "",
+    keywords: {
+      [style::Color::Yellow;
+        "true", "false", "null"
+      ]
+    },
+    multiline_comment: None::<(&'static str, &'static str)>,
+    colors: {
+      HighlightType::Normal => style::Color::Reset,
+      HighlightType::Number => style::Color::Cyan,
+      HighlightType::SearchMatch => style::Color::Blue,
+      HighlightType::DoubleQuoteString => style::Color::Green,
+      HighlightType::SingleQuoteString => style::Color::Green,
+      HighlightType::Comment => style::Color::DarkGrey,
+      HighlightType::MultilineComment => style::Color::DarkGrey,
+      HighlightType::MatchingBracket => style::Color::Magenta,
+      HighlightType::UnmatchedBracket => style::Color::DarkRed,
+      HighlightType::ObjectKey => style::Color::DarkCyan
+    },
+    formats: {
+      HighlightType::Other(_) => FormatType::Bold
+    },
+    separators: ['[', ']', '{', '}', ':']
+  }
+}
+
+syntax_struct! {
+  struct GoHighlight {
+    extensions: ["go"],
+    file_type: "Go",
+    comment_start: "//",
+    keywords: {
+      [style::Color::Red;
+        "break", "case", "chan", "const", "continue", "default", "defer", "else",
+        "fallthrough", "for", "func", "go", "goto", "if", "import", "interface", "map",
+        "package", "range", "return", "select", "struct", "switch", "type", "var",
+        "true", "false", "nil", "iota"
+      ],
+      [style::Color::Reset;
+        "bool", "byte", "complex64", "complex128", "error", "float32", "float64",
+        "int", "int8", "int16", "int32", "int64", "rune", "string",
+        "uint", "uint8", "uint16", "uint32", "uint64", "uintptr", "any"
+      ]
+    },
+    multiline_comment: Some(("/*", "*/")),
+    colors: {
+      HighlightType::Normal => style::Color::Reset,
+      HighlightType::Number => style::Color::Cyan,
+      HighlightType::SearchMatch => style::Color::Blue,
+      HighlightType::DoubleQuoteString => style::Color::Green,
+      HighlightType::SingleQuoteString => style::Color::Yellow,
+      HighlightType::Comment => style::Color::DarkGrey,
+      HighlightType::MultilineComment => style::Color::DarkGrey,
+      HighlightType::MatchingBracket => style::Color::Magenta,
+      HighlightType::UnmatchedBracket => style::Color::DarkRed
+    },
+    formats: {
+      HighlightType::Comment | HighlightType::MultilineComment => FormatType::Italic,
+      HighlightType::Other(_) => FormatType::Bold
+    }
+  }
+}
+
+syntax_struct! {
+  struct CHighlight {
+    extensions: ["c", "h"],
+    file_type: "C",
+    comment_start: "//",
+    keywords: {
+      [style::Color::Red;
+        "auto", "break", "case", "const", "continue", "default", "do", "else", "enum",
+        "extern", "for", "goto", "if", "inline", "register", "restrict", "return",
+        "sizeof", "static", "struct", "switch", "typedef", "union", "volatile", "while",
+        "#include", "#define", "#undef", "#if", "#ifdef", "#ifndef", "#elif", "#else",
+        "#endif", "#pragma", "NULL"
+      ],
+      [style::Color::Reset;
+        "char", "double", "float", "int", "long", "short", "signed", "unsigned", "void",
+        "size_t", "int8_t", "int16_t", "int32_t", "int64_t",
+        "uint8_t", "uint16_t", "uint32_t", "uint64_t", "bool"
+      ]
+    },
+    multiline_comment: Some(("/*", "*/")),
+    colors: {
+      HighlightType::Normal => style::Color::Reset,
+      HighlightType::Number => style::Color::Cyan,
+      HighlightType::SearchMatch => style::Color::Blue,
+      HighlightType::DoubleQuoteString => style::Color::Green,
+      HighlightType::SingleQuoteString => style::Color::Yellow,
+      HighlightType::Comment => style::Color::DarkGrey,
+      HighlightType::MultilineComment => style::Color::DarkGrey,
+      HighlightType::MatchingBracket => style::Color::Magenta,
+      HighlightType::UnmatchedBracket => style::Color::DarkRed
+    },
+    formats: {
+      HighlightType::Comment | HighlightType::MultilineComment => FormatType::Italic,
+      HighlightType::Other(_) => FormatType::Bold
+    }
+  }
+}
+
+syntax_struct! {
+  struct TomlHighlight {
+    extensions: ["toml"],
+    filenames: ["Cargo.lock"],
+    file_type: "TOML",
+    comment_start: "#",
+    keywords: {},
+    multiline_comment: None::<(&'static str, &'static str)>,
+    colors: {
+      HighlightType::Normal => style::Color::Reset,
+      HighlightType::Number => style::Color::Cyan,
+      HighlightType::SearchMatch => style::Color::Blue,
+      HighlightType::DoubleQuoteString => style::Color::Green,
+      HighlightType::SingleQuoteString => style::Color::Green,
+      HighlightType::Comment => style::Color::DarkGrey,
+      HighlightType::MultilineComment => style::Color::DarkGrey,
+      HighlightType::MatchingBracket => style::Color::Magenta,
+      HighlightType::UnmatchedBracket => style::Color::DarkRed,
+      HighlightType::ObjectKey => style::Color::DarkCyan,
+      HighlightType::Heading => style::Color::Yellow
+    },
+    formats: {
+      HighlightType::Comment => FormatType::Italic,
+      HighlightType::Heading => FormatType::Bold
+    },
+    line_keys: '='
+  }
+}
+
+syntax_struct! {
+  struct MakefileHighlight {
+    extensions: ["mk"],
+    filenames: ["Makefile", "makefile", "GNUmakefile"],
+    file_type: "Makefile",
+    comment_start: "#",
+    keywords: {
+      [style::Color::Yellow;
+        "ifeq", "ifneq", "ifdef", "ifndef", "else", "endif", "include", "define", "endef",
+        "export", "unexport", "override", "vpath", ".PHONY", ".SUFFIXES", ".DEFAULT"
+      ]
+    },
+    multiline_comment: None::<(&'static str, &'static str)>,
+    colors: {
+      HighlightType::Normal => style::Color::Reset,
+      HighlightType::Number => style::Color::Cyan,
+      HighlightType::SearchMatch => style::Color::Blue,
+      HighlightType::DoubleQuoteString => style::Color::Magenta,
+      HighlightType::SingleQuoteString => style::Color::DarkYellow,
+      HighlightType::Comment => style::Color::DarkGrey,
+      HighlightType::MultilineComment => style::Color::DarkGrey,
+      HighlightType::MatchingBracket => style::Color::Magenta,
+      HighlightType::UnmatchedBracket => style::Color::DarkRed
+    },
+    formats: {
+      HighlightType::Comment => FormatType::Italic,
+      HighlightType::Other(_) => FormatType::Bold
+    }
+  }
+}
+
+syntax_struct! {
+  struct DockerfileHighlight {
+    extensions: ["dockerfile"],
+    filenames: ["Dockerfile", "Containerfile"],
+    file_type: "Dockerfile",
+    comment_start: "#",
+    keywords: {
+      [style::Color::Yellow;
+        "FROM", "AS", "RUN", "CMD", "LABEL", "EXPOSE", "ENV", "ADD", "COPY", "ENTRYPOINT",
+        "VOLUME", "USER", "WORKDIR", "ARG", "ONBUILD", "STOPSIGNAL", "HEALTHCHECK", "SHELL"
+      ]
+    },
+    multiline_comment: None::<(&'static str, &'static str)>,
+    colors: {
+      HighlightType::Normal => style::Color::Reset,
+      HighlightType::Number => style::Color::Cyan,
+      HighlightType::SearchMatch => style::Color::Blue,
+      HighlightType::DoubleQuoteString => style::Color::Green,
+      HighlightType::SingleQuoteString => style::Color::Green,
+      HighlightType::Comment => style::Color::DarkGrey,
+      HighlightType::MultilineComment => style::Color::DarkGrey,
+      HighlightType::MatchingBracket => style::Color::Magenta,
+      HighlightType::UnmatchedBracket => style::Color::DarkRed
+    },
+    formats: {
+      HighlightType::Comment => FormatType::Italic,
+      HighlightType::Other(_) => FormatType::Bold
+    }
+  }
+}
+
+// Markdown is about line prefixes and inline markers rather than keywords,
+// so it gets its own highlight_row instead of going through syntax_struct!
+pub struct MarkdownHighlight {
+  extensions: &'static [&'static str],
+  file_type: &'static str,
+}
+
+impl MarkdownHighlight {
+  pub fn new() -> Self {
+    Self {
+      extensions: &["md", "markdown"],
+      file_type: "Markdown",
+    }
+  }
+
+  fn is_fence(render: &str) -> bool {
+    let line = render.trim_start();
+    line.starts_with("```") || line.starts_with("~~~")
+  }
+
+  fn is_heading(render: &str) -> bool {
+    let level = render.bytes().take_while(|&b| b == b'#').count();
+    (1..=6).contains(&level) && render[level..].chars().next().is_none_or(char::is_whitespace)
+  }
+
+  // Highlights `code` spans and *emphasis*, **strong** or _emphasis_ runs
+  fn highlight_inline(render: &[u8], highlight: &mut [HighlightType]) {
+    let mut i = 0;
+    while i < render.len() {
+      let marker = render[i];
+      let run = render[i..].iter().take_while(|&&b| b == marker).count();
+      let closing = match marker {
+        b'`' => Self::find_run(render, i + run, marker, run).map(|end| (end, HighlightType::CodeSpan)),
+        // Underscores inside words are part of names like snake_case
+        b'*' | b'_' if run <= 2
+          && !(marker == b'_' && i > 0 && render[i - 1].is_ascii_alphanumeric())
+          && render.get(i + run).is_some_and(|b| !b.is_ascii_whitespace()) => {
+          Self::find_run(render, i + run, marker, run).map(|end| (end, HighlightType::Emphasis))
+        },
+        _ => None,
+      };
+      match closing {
+        Some((end, highlight_type)) => {
+          highlight[i..end].iter_mut().for_each(|highlight| *highlight = highlight_type);
+          i = end;
+        },
+        None => i += run,
+      }
+    }
+  }
+
+  // End of the next run of exactly `length` markers, at or after `from`
+  fn find_run(render: &[u8], from: usize, marker: u8, length: usize) -> Option<usize> {
+    let mut i = from;
+    while i < render.len() {
+      let run = render[i..].iter().take_while(|&&b| b == marker).count();
+      if run == length {
+        return Some(i + run);
+      }
+      i += cmp::max(run, 1);
+    }
+    None
+  }
+}
+
+impl SyntaxHighlight for MarkdownHighlight {
+  fn extensions(&self) -> &[&str] {
+    self.extensions
+  }
+
+  fn file_type(&self) -> &str {
+    self.file_type
+  }
+
+  fn comment_start(&self) -> &str {
+    ""
+  }
+
+  fn multiline_comment(&self) -> Option<(&str, &str)> {
+    Some(("```", "```"))
+  }
+
+  fn highlight_row(&self, at: usize, editor_rows: &mut [Row]) -> bool {
+    if at >= editor_rows.len() {
+      return false;
+    }
+    // `is_comment` marks rows that end inside a fenced code block
+    let in_fence = at > 0 && editor_rows[at - 1].is_comment;
+    let current_row = &mut editor_rows[at];
+    let render = current_row.render.as_str();
+
+    let is_fence = Self::is_fence(render);
+    let highlight_type = if is_fence || in_fence {
+      HighlightType::MultilineComment
+    } else if Self::is_heading(render) {
+      HighlightType::Heading
+    } else {
+      HighlightType::Normal
+    };
+    current_row.highlight = vec![highlight_type; render.len()];
+    if !in_fence && !is_fence && matches!(highlight_type, HighlightType::Normal) {
+      Self::highlight_inline(render.as_bytes(), &mut current_row.highlight);
+    }
+
+    let ends_in_fence = in_fence != is_fence;
+    let changed = current_row.is_comment != ends_in_fence;
+    current_row.is_comment = ends_in_fence;
+    changed
+  }
+
+  fn syntax_color(&self, highlight_type: &HighlightType) -> style::Color {
+    if let Some(color) = theme::color(highlight_type) {
+      return color;
+    }
+    match highlight_type {
+      HighlightType::SearchMatch => style::Color::Blue,
+      HighlightType::MultilineComment => style::Color::DarkGrey,
+      HighlightType::MatchingBracket => style::Color::Magenta,
+      HighlightType::UnmatchedBracket => style::Color::DarkRed,
+      HighlightType::Heading => style::Color::Cyan,
+      HighlightType::Emphasis => style::Color::Yellow,
+      HighlightType::CodeSpan => style::Color::Green,
+      HighlightType::Other(color) => *color,
+      _ => style::Color::Reset,
+    }
+  }
+
+  fn syntax_format(&self, highlight_type: &HighlightType) -> FormatType {
+    match highlight_type {
+      HighlightType::Heading => FormatType::Bold,
+      HighlightType::Emphasis => FormatType::Italic,
+      _ => FormatType::Normal,
+    }
+  }
+}
+
+// YAML keys are found by where they sit, before the `: ` at the start of a line,
+// so like Markdown it gets its own highlight_row
+pub struct YamlHighlight {
+  extensions: &'static [&'static str],
+  file_type: &'static str,
+}
+
+impl YamlHighlight {
+  pub fn new() -> Self {
+    Self {
+      extensions: &["yaml", "yml"],
+      file_type: "YAML",
+    }
+  }
+
+  // Index of the `:` ending the key that starts at `start`. A quoted key can hold
+  // colons of its own
+  fn key_end(render: &[u8], start: usize) -> Option<usize> {
+    let mut i = match render.get(start)? {
+      b'"' | b'\'' => Self::string_end(render, start),
+      b'[' | b'{' | b'#' => return None,
+      _ => start,
+    };
+    while i < render.len() {
+      match render[i] {
+        b':' if render.get(i + 1).is_none_or(u8::is_ascii_whitespace) => return Some(i),
+        b'#' if render[i - 1].is_ascii_whitespace() => return None,
+        _ => i += 1,
+      }
+    }
+    None
+  }
+
+  // Just past the quote closing the string that starts at `start`, or the end of the line
+  fn string_end(render: &[u8], start: usize) -> usize {
+    let quote = render[start];
+    let mut i = start + 1;
+    while i < render.len() {
+      match render[i] {
+        // A doubled quote is how single quoted strings escape one
+        b'\'' if quote == b'\'' && render.get(i + 1) == Some(&b'\'') => i += 2,
+        b'\\' if quote == b'"' => i += 2,
+        c if c == quote => return i + 1,
+        _ => i += 1,
+      }
+    }
+    render.len()
+  }
+
+  // A plain value runs to a comment, or inside `[]` and `{}` to the next separator
+  fn plain_end(render: &[u8], start: usize, in_flow: bool) -> usize {
+    let mut i = start + 1;
+    while i < render.len() {
+      let c = render[i];
+      let is_end = (c == b'#' && render[i - 1].is_ascii_whitespace())
+        || (in_flow && (b",[]{}".contains(&c) || (c == b':' && render.get(i + 1).is_none_or(u8::is_ascii_whitespace))));
+      if is_end {
+        break;
+      }
+      i += 1;
+    }
+    i
+  }
+
+  fn plain_highlight(value: &[u8]) -> Option<HighlightType> {
+    let is_keyword = ["true", "false", "null"]
+      .iter()
+      .any(|keyword| value.eq_ignore_ascii_case(keyword.as_bytes()));
+    if is_keyword || value == b"~" {
+      return Some(HighlightType::Other(style::Color::Yellow));
+    }
+    let digits = value.strip_prefix(b"-").or_else(|| value.strip_prefix(b"+")).unwrap_or(value);
+    let is_number = digits.first().is_some_and(u8::is_ascii_digit) && number_length(digits) == Some(digits.len());
+    is_number.then_some(HighlightType::Number)
+  }
+
+  // Highlights what follows the key, or the whole line when it has none
+  fn highlight_value(render: &[u8], from: usize, highlight: &mut [HighlightType]) {
+    let mut fill = |range: std::ops::Range<usize>, highlight_type: HighlightType| {
+      highlight[range].iter_mut().for_each(|highlight| *highlight = highlight_type);
+    };
+    let mut flow_depth = 0usize;
+    let mut i = from;
+    while i < render.len() {
+      let c = render[i];
+      match c {
+        _ if c.is_ascii_whitespace() => i += 1,
+        b'#' if i == 0 || render[i - 1].is_ascii_whitespace() => {
+          fill(i..render.len(), HighlightType::Comment);
+          break;
+        },
+        b'[' | b'{' => {
+          flow_depth += 1;
+          i += 1;
+        },
+        b']' | b'}' | b',' | b':' if flow_depth > 0 => {
+          if matches!(c, b']' | b'}') {
+            flow_depth -= 1;
+          }
+          i += 1;
+        },
+        b'"' | b'\'' => {
+          let end = Self::string_end(render, i);
+          fill(i..end, if c == b'"' { HighlightType::DoubleQuoteString } else { HighlightType::SingleQuoteString });
+          i = end;
+        },
+        // `&name` anchors a value and `*name` refers back to it
+        b'&' | b'*' => {
+          let end = render[i + 1..]
+            .iter()
+            .position(|b| b.is_ascii_whitespace() || b",[]{}".contains(b))
+            .map_or(render.len(), |length| i + 1 + length);
+          fill(i..end, HighlightType::Other(style::Color::Magenta));
+          i = end;
+        },
+        _ => {
+          let end = Self::plain_end(render, i, flow_depth > 0);
+          let value = render[i..end].trim_ascii_end();
+          if let Some(highlight_type) = Self::plain_highlight(value) {
+            fill(i..i + value.len(), highlight_type);
+          }
+          i = end;
+        },
+      }
+    }
+  }
+}
+
+impl SyntaxHighlight for YamlHighlight {
+  fn extensions(&self) -> &[&str] {
+    self.extensions
+  }
+
+  fn file_type(&self) -> &str {
+    self.file_type
+  }
+
+  fn comment_start(&self) -> &str {
+    "#"
+  }
+
+  fn multiline_comment(&self) -> Option<(&str, &str)> {
+    None
+  }
+
+  fn highlight_row(&self, at: usize, editor_rows: &mut [Row]) -> bool {
+    if at >= editor_rows.len() {
+      return false;
+    }
+    let current_row = &mut editor_rows[at];
+    let render = current_row.render.as_bytes();
+    let highlight = &mut current_row.highlight;
+    *highlight = vec![HighlightType::Normal; render.len()];
+
+    let mut i = 0;
+    // `---` and `...` start and end documents
+    if matches!(render.get(..3), Some(b"---" | b"...")) && render.get(3).is_none_or(u8::is_ascii_whitespace) {
+      highlight[..3].iter_mut().for_each(|highlight| *highlight = HighlightType::Heading);
+      i = 3;
+    }
+    // Past the indentation and any `- ` list markers, since `- name: value` still has a key
+    while i < render.len() {
+      let is_marker = render[i] == b'-' && render.get(i + 1).is_none_or(u8::is_ascii_whitespace);
+      if !render[i].is_ascii_whitespace() && !is_marker {
+        break;
+      }
+      i += 1;
+    }
+    if let Some(end) = Self::key_end(render, i) {
+      let key = render[i..end].trim_ascii_end();
+      highlight[i..i + key.len()].iter_mut().for_each(|highlight| *highlight = HighlightType::ObjectKey);
+      i = end + 1;
+    }
+    Self::highlight_value(render, i, highlight);
+
+    // Nothing carries over from one line to the next
+    let changed = current_row.is_comment;
+    current_row.is_comment = false;
+    changed
+  }
+
+  fn syntax_color(&self, highlight_type: &HighlightType) -> style::Color {
+    if let Some(color) = theme::color(highlight_type) {
+      return color;
+    }
+    match highlight_type {
+      HighlightType::Number => style::Color::Cyan,
+      HighlightType::SearchMatch => style::Color::Blue,
+      HighlightType::DoubleQuoteString | HighlightType::SingleQuoteString => style::Color::Green,
+      HighlightType::Comment => style::Color::DarkGrey,
+      HighlightType::MatchingBracket => style::Color::Magenta,
+      HighlightType::UnmatchedBracket => style::Color::DarkRed,
+      HighlightType::ObjectKey => style::Color::DarkCyan,
+      HighlightType::Heading => style::Color::Yellow,
+      HighlightType::Other(color) => *color,
+      _ => style::Color::Reset,
+    }
+  }
+
+  fn syntax_format(&self, highlight_type: &HighlightType) -> FormatType {
+    match highlight_type {
+      HighlightType::Comment => FormatType::Italic,
+      HighlightType::Heading | HighlightType::Other(_) => FormatType::Bold,
+      _ => FormatType::Normal,
     }
   }
-}
\ No newline at end of file
+}
@@ -0,0 +1,72 @@
+// Character classes shared by the highlighters, word motions and anything else that
+// needs to know where words begin and end, and how many columns text takes up
+use unicode_width::UnicodeWidthChar;
+
+// Full-width characters take two terminal columns, anything without a width of its own one
+pub fn char_width(c: char) -> usize {
+  c.width().unwrap_or(1).max(1)
+}
+
+pub fn width(text: &str) -> usize {
+  if text.is_ascii() {
+    return text.len();
+  }
+  text.chars().map(char_width).sum()
+}
+
+// Columns from `column` to the next tab stop
+pub fn tab_width(column: usize, tab_stop: usize) -> usize {
+  tab_stop - column % tab_stop
+}
+
+// The longest prefix of `text` that fits in `columns`
+pub fn truncate(text: &str, columns: usize) -> &str {
+  let mut used = 0;
+  for (index, c) in text.char_indices() {
+    used += char_width(c);
+    if used > columns {
+      return &text[..index];
+    }
+  }
+  text
+}
+
+pub fn is_separator(c: char) -> bool {
+  c.is_whitespace() || [
+    ',', '.', '(', ')', '+', '-', '/', '*', '=', '~', '%', '<', '>', '"', '\'', ';', '&',
+  ].contains(&c)
+}
+
+// Like vim, runs of word characters and runs of punctuation are separate words
+#[derive(PartialEq)]
+pub enum CharClass {
+  Blank,
+  Separator,
+  Word,
+}
+
+impl CharClass {
+  pub fn of(c: char) -> Self {
+    if c.is_whitespace() {
+      CharClass::Blank
+    } else if is_separator(c) {
+      CharClass::Separator
+    } else {
+      CharClass::Word
+    }
+  }
+}
+
+// Char indices where each word in the line starts
+pub fn word_boundaries(line: &str) -> Vec<usize> {
+  let mut previous = CharClass::Blank;
+  line.chars()
+    .enumerate()
+    .filter_map(|(i, c)| {
+      let class = CharClass::of(c);
+      let starts_word = class != CharClass::Blank && class != previous;
+      previous = class;
+      starts_word.then_some(i)
+    })
+    .collect()
+}
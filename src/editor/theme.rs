@@ -0,0 +1,95 @@
+// Colors from a theme file that take the place of the highlighters' own, so they can
+// match the terminal's palette. Themes live in ~/.vimrs/themes/<name>.theme
+use std::collections::HashMap;
+use std::sync::{LazyLock, RwLock};
+use std::{env, fs};
+use std::path::PathBuf;
+use crossterm::style::Color;
+
+use crate::{log, CONFIG};
+use super::highlight::HighlightType;
+
+// The theme in use, "default" leaves every color to the highlighter
+static THEME: LazyLock<RwLock<HashMap<&'static str, Color>>> = LazyLock::new(|| {
+  let colors = CONFIG.theme.as_deref().map_or_else(|| Ok(HashMap::new()), load);
+  RwLock::new(colors.unwrap_or_else(|error| {
+    log::log::log("WARN".to_string(), error);
+    HashMap::new()
+  }))
+});
+
+const KEYS: [&str; 13] = [
+  "normal", "number", "search", "string", "char", "comment", "multiline_comment",
+  "matching_bracket", "unmatched_bracket", "key", "heading", "emphasis", "code",
+];
+
+// Theme keys for each kind of highlight, keywords keep their language's colors
+fn key(highlight_type: &HighlightType) -> Option<&'static str> {
+  Some(match highlight_type {
+    HighlightType::Normal => "normal",
+    HighlightType::Number => "number",
+    HighlightType::SearchMatch => "search",
+    HighlightType::DoubleQuoteString => "string",
+    HighlightType::SingleQuoteString => "char",
+    HighlightType::Comment => "comment",
+    HighlightType::MultilineComment => "multiline_comment",
+    HighlightType::MatchingBracket => "matching_bracket",
+    HighlightType::UnmatchedBracket => "unmatched_bracket",
+    HighlightType::ObjectKey => "key",
+    HighlightType::Heading => "heading",
+    HighlightType::Emphasis => "emphasis",
+    HighlightType::CodeSpan => "code",
+    HighlightType::Other(_) => return None,
+  })
+}
+
+pub fn color(highlight_type: &HighlightType) -> Option<Color> {
+  let theme = THEME.read().ok()?;
+  theme.get(key(highlight_type)?).copied()
+}
+
+// `:colorscheme`, switching to a theme file or back to the built-in colors
+pub fn set(name: &str) -> Result<(), String> {
+  let colors = if name == "default" { HashMap::new() } else { load(name)? };
+  *THEME.write().map_err(|error| error.to_string())? = colors;
+  Ok(())
+}
+
+fn theme_file(name: &str) -> Option<PathBuf> {
+  env::var_os("HOME").map(|home| PathBuf::from(home).join(".vimrs").join("themes").join(format!("{}.theme", name)))
+}
+
+// One `key = color` per line, where a color is a name like `dark_cyan` or `#RRGGBB`
+fn load(name: &str) -> Result<HashMap<&'static str, Color>, String> {
+  let contents = theme_file(name)
+    .and_then(|file| fs::read_to_string(file).ok())
+    .ok_or_else(|| format!("Cannot find color scheme '{}'", name))?;
+  let mut colors = HashMap::new();
+  for line in contents.lines().map(str::trim) {
+    if line.is_empty() || line.starts_with('#') {
+      continue;
+    }
+    let parsed = line.split_once('=').and_then(|(key, value)| {
+      let key = KEYS.iter().find(|&&name| name == key.trim())?;
+      Some((*key, parse_color(value.trim())?))
+    });
+    match parsed {
+      Some((key, color)) => {
+        colors.insert(key, color);
+      },
+      None => log::log::log("WARN".to_string(), format!("Invalid theme line in {}: {:?}", name, line)),
+    }
+  }
+  Ok(colors)
+}
+
+fn parse_color(value: &str) -> Option<Color> {
+  let Some(hex) = value.strip_prefix('#') else {
+    return Color::try_from(value).ok();
+  };
+  if hex.len() != 6 {
+    return None;
+  }
+  let channel = |at: usize| u8::from_str_radix(hex.get(at..at + 2)?, 16).ok();
+  Some(Color::Rgb { r: channel(0)?, g: channel(2)?, b: channel(4)? })
+}
@@ -1,17 +1,71 @@
-use std::{io, time};
-use crossterm::{event, terminal, queue};
-use crossterm::event::{Event, KeyEvent};
+#![allow(clippy::module_inception, clippy::new_without_default)]
+
+use std::{env, fs, io, panic, thread, time};
+use std::collections::{HashMap, VecDeque};
+use std::path::PathBuf;
+use std::sync::LazyLock;
+use crossterm::{cursor, event, terminal, execute};
+use crossterm::event::{Event, KeyEvent, MouseButton, MouseEvent, MouseEventKind};
 
 pub mod editor {
   pub mod output;
+  pub mod buffers;
   pub mod cursor;
   pub mod editor;
   pub mod highlight;
   pub mod syntax;
+  pub mod clipboard;
+  pub mod substitute;
+  pub mod options;
+  pub mod text;
+  pub mod state;
+  pub mod swap;
+  pub mod theme;
+  pub mod history;
+  pub mod shell;
+  pub mod spell;
+  pub mod search;
 }
 mod log;
 
-use editor::output::Output;
+pub use editor::{editor::Editor, output::Output};
+
+// Starts the editor on the command line's file and runs it until it quits, with the
+// terminal put back however it ends
+pub fn run() -> crossterm::Result<()> {
+  if let Some(info) = command_line_info() {
+    println!("{}", info);
+    return Ok(());
+  }
+
+  let _clean_up = CleanUp::new();
+  let mut editor = Editor::new()?;
+  while editor.run()? {}
+  Ok(())
+}
+
+// Keys that drive the editor, remappable for other keyboard layouts
+pub struct Keymap {
+  pub command: char,
+  pub insert: char,
+  pub visual: char,
+  pub write: char,
+  pub quit: char,
+  pub find: char,
+}
+
+impl Default for Keymap {
+  fn default() -> Self {
+    Self {
+      command: ':',
+      insert: 'i',
+      visual: 'v',
+      write: 'w',
+      quit: 'q',
+      find: 'f',
+    }
+  }
+}
 
 pub struct Config {
   pub version: f32,
@@ -19,45 +73,205 @@ pub struct Config {
   pub spaces_per_tab: usize,
   pub message_timeout: u64,
   pub max_new_filename_length: usize,
-  pub line_number_color: &'static str,
-  pub tilde_color: &'static str,
-  // command_character: KeyCode,
-}
-
-pub const CONFIG: Config = Config {
-  version: 1.00,
-  poll_timeout: time::Duration::from_millis(1500),
-  spaces_per_tab: 2,
-  message_timeout: 5,
-  max_new_filename_length: 32,
-  line_number_color: "red",
-  tilde_color: "purple",
-  // command_character: KeyCode::Char(':'), // TODO- Actually use this
-};
+  pub line_number_color: String,
+  pub tilde_color: String,
+  pub backup: bool,
+  pub swap_interval: time::Duration,
+  pub theme: Option<String>,
+  pub spell_file: Option<String>,
+  pub log_level: log::log::Level,
+  pub abbreviations: HashMap<String, String>, // Trigger word to expansion
+  pub keymap: Keymap,
+}
+
+impl Default for Config {
+  fn default() -> Self {
+    Self {
+      version: 1.00,
+      poll_timeout: time::Duration::from_millis(1500),
+      spaces_per_tab: 2,
+      message_timeout: 5,
+      max_new_filename_length: 32,
+      line_number_color: "red".to_string(),
+      tilde_color: "purple".to_string(),
+      backup: false,
+      swap_interval: time::Duration::from_secs(10),
+      theme: None,
+      spell_file: None,
+      log_level: log::log::Level::Info,
+      abbreviations: HashMap::new(),
+      keymap: Keymap::default(),
+    }
+  }
+}
+
+impl Config {
+  // Reads $VIMRS_CONFIG, or ~/.vimrsrc, falling back to the defaults
+  pub fn load() -> Self {
+    let mut config = Self::default();
+    let path = env::var_os("VIMRS_CONFIG")
+      .map(PathBuf::from)
+      .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".vimrsrc")));
+
+    if let Some(contents) = path.and_then(|path| fs::read_to_string(path).ok()) {
+      config.parse(&contents);
+    }
+    // $VIMRS_LOG takes precedence over the config file
+    if let Some(level) = env::var("VIMRS_LOG").ok().and_then(|level| log::log::Level::parse(&level)) {
+      config.log_level = level;
+    }
+    log::log::set_threshold(config.log_level);
+    config
+  }
+
+  fn parse(&mut self, contents: &str) {
+    for line in contents.lines().map(str::trim) {
+      if line.is_empty() || line.starts_with('#') {
+        continue;
+      }
+      let Some((key, value)) = line.split_once('=') else {
+        log::log::log("WARN".to_string(), format!("Malformed config line: {:?}", line));
+        continue;
+      };
+      let (key, value) = (key.trim(), value.trim());
+
+      let valid = match key {
+        "poll_timeout" => value
+          .parse()
+          .map(|millis| self.poll_timeout = time::Duration::from_millis(millis))
+          .is_ok(),
+        "spaces_per_tab" => value
+          .parse()
+          .ok()
+          .filter(|spaces| *spaces > 0)
+          .map(|spaces| self.spaces_per_tab = spaces)
+          .is_some(),
+        "message_timeout" => value
+          .parse()
+          .map(|seconds| self.message_timeout = seconds)
+          .is_ok(),
+        "max_new_filename_length" => value
+          .parse()
+          .map(|length| self.max_new_filename_length = length)
+          .is_ok(),
+        "line_number_color" => {
+          self.line_number_color = value.to_string();
+          true
+        },
+        "tilde_color" => {
+          self.tilde_color = value.to_string();
+          true
+        },
+        "backup" => value
+          .parse()
+          .map(|backup| self.backup = backup)
+          .is_ok(),
+        "swap_interval" => value
+          .parse()
+          .map(|seconds| self.swap_interval = time::Duration::from_secs(seconds))
+          .is_ok(),
+        "theme" => {
+          self.theme = Some(value.to_string());
+          true
+        },
+        "spell_file" => {
+          self.spell_file = Some(value.to_string());
+          true
+        },
+        "log_level" => log::log::Level::parse(value)
+          .map(|level| self.log_level = level)
+          .is_some(),
+        "abbreviation" => Self::parse_abbreviation(value)
+          .map(|(trigger, expansion)| self.abbreviations.insert(trigger, expansion))
+          .is_some(),
+        "command_key" => Self::parse_key(value).map(|key| self.keymap.command = key).is_some(),
+        "insert_key" => Self::parse_key(value).map(|key| self.keymap.insert = key).is_some(),
+        "visual_key" => Self::parse_key(value).map(|key| self.keymap.visual = key).is_some(),
+        "write_key" => Self::parse_key(value).map(|key| self.keymap.write = key).is_some(),
+        "quit_key" => Self::parse_key(value).map(|key| self.keymap.quit = key).is_some(),
+        "find_key" => Self::parse_key(value).map(|key| self.keymap.find = key).is_some(),
+        _ => {
+          log::log::log("WARN".to_string(), format!("Unknown config key: {:?}", key));
+          continue;
+        },
+      };
+      if !valid {
+        log::log::log("WARN".to_string(), format!("Invalid value for {}: {:?}", key, value));
+      }
+    }
+  }
+
+  // `trigger expansion`, where the expansion can hold `\n` and `\t` and the trigger
+  // is a single word
+  fn parse_abbreviation(value: &str) -> Option<(String, String)> {
+    let (trigger, expansion) = value.split_once(char::is_whitespace)?;
+    if trigger.chars().any(editor::text::is_separator) {
+      return None;
+    }
+    let expansion = expansion.trim_start().replace("\\n", "\n").replace("\\t", "\t");
+    Some((trigger.to_string(), expansion))
+  }
+
+  // Keys are a single printable character
+  fn parse_key(value: &str) -> Option<char> {
+    let mut chars = value.chars();
+    match (chars.next(), chars.next()) {
+      (Some(key), None) if !key.is_whitespace() && !key.is_control() => Some(key),
+      _ => None,
+    }
+  }
+}
+
+pub static CONFIG: LazyLock<Config> = LazyLock::new(Config::load);
 
 #[macro_export]
 macro_rules! prompt {
   ($output:expr, $args:tt) => {
     prompt!($output, $args, callback = |&_, _, _| {})
   };
-  ($output:expr, $args:tt, callback = $callback:expr) => {{
+  ($output:expr, $args:tt, callback = $callback:expr) => {
+    prompt!($output, $args, callback = $callback, max_length = None)
+  };
+  ($output:expr, $args:tt, max_length = $max_length:expr) => {
+    prompt!($output, $args, callback = |&_, _, _| {}, max_length = Some($max_length))
+  };
+  // Typing past `max_length` chars is refused with a warning after the prompt
+  ($output:expr, $args:tt, callback = $callback:expr, max_length = $max_length:expr) => {{
     let output: &mut Output = $output;
-    let mut input = String::with_capacity(CONFIG.max_new_filename_length);
+    let max_length: Option<usize> = $max_length;
+    let mut input = String::with_capacity(max_length.unwrap_or_default());
+    let mut warning: Option<String> = None;
+    // Taken out of `output` while prompting, each prompt keeps its own entries
+    let mut history = output.prompt_history.remove($args).unwrap_or_else($crate::editor::history::History::new);
     loop {
-      output.status_message.set_message(format!($args, input));
+      let message = format!($args, input);
+      output.status_message.set_message(match &warning {
+        Some(warning) => format!("{}  {}", message, warning),
+        None => message,
+      });
       output.refresh_screen()?;
-      let key_event = Reader.read()?;
+      // A timeout redraws the prompt too, like the main loop's ticks
+      let key_event = match $crate::InputSource::poll(&mut *output.input)? {
+        None => continue,
+        Some(event::Event::Resize(columns, rows)) => {
+          output.resize(columns, rows);
+          continue;
+        },
+        Some(event::Event::Key(key_event)) => key_event,
+        Some(_) => continue,
+      };
+      // The warning stays up until the next key
+      warning = None;
       match key_event {
         KeyEvent {
           code: KeyCode::Enter,
           modifiers: event::KeyModifiers::NONE,
           ..
-        } => {
-          if !input.is_empty() {
-            output.status_message.set_message(String::new());
-            $callback(output, &input, KeyCode::Enter);
-            break;
-          }
+        } if !input.is_empty() => {
+          output.status_message.set_message(String::new());
+          history.push(&input);
+          $callback(output, &input, KeyCode::Enter);
+          break;
         },
         KeyEvent {
           code: KeyCode::Esc,
@@ -65,6 +279,7 @@ macro_rules! prompt {
         } => {
           output.status_message.set_message(String::new());
           input.clear();
+          history.reset();
           $callback(output, &input, KeyCode::Esc);
           break;
         }
@@ -78,6 +293,28 @@ macro_rules! prompt {
             None => {},
           }
         },
+        // Arrow keys are left to the callback, the search moves between matches with them
+        KeyEvent {
+          code: KeyCode::Char(direction @ ('p' | 'n')),
+          modifiers: event::KeyModifiers::CONTROL,
+          ..
+        } => {
+          let recalled = match direction {
+            'p' => history.older(),
+            _ => history.newer(),
+          };
+          if let Some(recalled) = recalled {
+            input = recalled.to_string();
+          }
+        },
+        KeyEvent {
+          code: KeyCode::Char(..) | KeyCode::Tab,
+          modifiers: event::KeyModifiers::NONE | event::KeyModifiers::SHIFT,
+          ..
+        } if max_length.is_some_and(|max_length| input.chars().count() >= max_length) => {
+          warning = max_length.map(|max_length| format!("(at most {} characters)", max_length));
+          continue;
+        },
         KeyEvent {
           code: code @ (KeyCode::Char(..) | KeyCode::Tab),
           modifiers: event::KeyModifiers::NONE | event::KeyModifiers::SHIFT,
@@ -91,6 +328,7 @@ macro_rules! prompt {
       }
       $callback(output, &input, key_event.code);
     }
+    output.prompt_history.insert($args, history);
     if input.is_empty() { None } else { Some(input) }
   }};
 }
@@ -100,15 +338,82 @@ macro_rules! prompt {
     CLEAN UP STRUCTURE
 
 */
+// `--version` and `--help` are answered before the terminal is touched
+fn command_line_info() -> Option<String> {
+  let flag = env::args().skip(1).find(|arg| matches!(arg.as_str(), "--version" | "-h" | "--help"))?;
+  if flag == "--version" {
+    return Some(format!("vimrs {}", CONFIG.version));
+  }
+  let keymap = &CONFIG.keymap;
+  let command = keymap.command;
+  Some(format!(
+    "\
+Usage: vimrs [options] [file[:line]]
+
+Options:
+  +N              Start on line N, a bare + starts on the last line
+  -               Edit text read from stdin
+  -R, --readonly  Refuse to write the file without !
+  -h, --help      Show this help
+  --version       Show the version
+
+Modes:
+  {insert} a A I o O     Insert text, Esc goes back
+  {visual}               Select text
+  {command}               Enter a command
+
+Commands:
+  {command}{write}              Write the file, {command}{write}! even when read-only
+  {command}{quit}              Quit, {command}{quit}! throws away unsaved changes
+  {command}{write}{quit} or ZZ       Write the file and quit
+  {command}{find}              Search, arrow keys move between matches
+                  and Ctrl-P/Ctrl-N recall earlier searches
+  {command}N              Go to line N
+  {command}s/old/new/gc   Replace on this line, {command}%s on every line
+  {command}e file         Open a file, {command}e! reloads this one
+  {command}set option     Change an option, like number or wrap
+",
+    insert = keymap.insert,
+    visual = keymap.visual,
+    command = command,
+    write = keymap.write,
+    quit = keymap.quit,
+    find = keymap.find,
+  ))
+}
+
 pub struct CleanUp;
 
+impl CleanUp {
+  // Create this before the editor, so the terminal is restored however the program ends
+  pub fn new() -> Self {
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+      // Restore the terminal first so the panic message is readable
+      let _ = Self::restore_terminal();
+      default_hook(info);
+    }));
+    Self
+  }
+
+  fn restore_terminal() -> crossterm::Result<()> {
+    // Hand back whatever cursor shape the terminal is configured with
+    execute!(io::stdout(), event::DisableMouseCapture, cursor::SetCursorStyle::DefaultUserShape)?;
+    terminal::disable_raw_mode()?;
+    execute!(io::stdout(), terminal::LeaveAlternateScreen)
+  }
+}
+
 impl Drop for CleanUp {
   // Implement Drop for this struct so that when it goes out of scope,
   // this function automatically runs
   fn drop(&mut self) {
+    // The panic hook has already restored the terminal, and clearing would hide its message
+    if thread::panicking() {
+      return;
+    }
     log::log::log("INFO".to_string(), "Cleaning up.".to_string());
-    terminal::disable_raw_mode().expect("Failed to disable RAW mode.");
-    queue!(io::stdout(), terminal::LeaveAlternateScreen).expect("Failed to leave alternate screen.");
+    Self::restore_terminal().expect("Failed to restore the terminal.");
     Output::clear_screen().expect("Failed to clear screen.");
   }
 }
@@ -118,16 +423,58 @@ impl Drop for CleanUp {
     READER STRUCTURE
 
 */
-pub struct Reader;
+// Where the editor's events come from, the terminal or a script of keys when testing
+pub trait InputSource {
+  // The next event, or `None` once `CONFIG.poll_timeout` passes without one
+  fn poll(&mut self) -> crossterm::Result<Option<Event>>;
 
-impl Reader {
-  pub fn read(&self) -> crossterm::Result<KeyEvent> {
+  fn read(&mut self) -> crossterm::Result<Event> {
     loop {
-      if event::poll(CONFIG.poll_timeout)? {
-        if let Event::Key(event) = event::read()? {
-          return Ok(event);
-        }
+      if let Some(event) = self.poll()? {
+        return Ok(event);
+      }
+    }
+  }
+}
+
+pub struct Reader;
+
+impl InputSource for Reader {
+  // Returns key presses, terminal resizes, clicks and the scroll wheel, ignoring everything else
+  fn poll(&mut self) -> crossterm::Result<Option<Event>> {
+    if event::poll(CONFIG.poll_timeout)? {
+      match event::read()? {
+        event @ (Event::Key(_) | Event::Resize(..)) => return Ok(Some(event)),
+        event @ Event::Mouse(MouseEvent {
+          kind: MouseEventKind::Down(MouseButton::Left) | MouseEventKind::ScrollUp | MouseEventKind::ScrollDown,
+          ..
+        }) => return Ok(Some(event)),
+        _ => {},
       }
     }
+    Ok(None)
+  }
+}
+
+// Keys handed to the editor in order, so it can be driven without a terminal. Running
+// out is an error rather than a wait that would never end
+pub struct ScriptedInput {
+  events: VecDeque<Event>,
+}
+
+impl ScriptedInput {
+  pub fn new(keys: Vec<KeyEvent>) -> Self {
+    Self {
+      events: keys.into_iter().map(Event::Key).collect(),
+    }
+  }
+}
+
+impl InputSource for ScriptedInput {
+  fn poll(&mut self) -> crossterm::Result<Option<Event>> {
+    match self.events.pop_front() {
+      Some(event) => Ok(Some(event)),
+      None => Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Ran out of scripted input.")),
+    }
   }
 }
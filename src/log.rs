@@ -1,21 +1,65 @@
 pub mod log {
+  use std::fs::{self, File};
+  use std::io::Write;
+  use std::sync::atomic::{AtomicU8, Ordering};
+  use std::sync::{Mutex, OnceLock};
+
+  #[derive(Clone, Copy, PartialEq, PartialOrd)]
+  pub enum Level {
+    Off,
+    Error,
+    Warn,
+    Info,
+    Debug,
+  }
+
+  impl Level {
+    pub fn parse(name: &str) -> Option<Self> {
+      Some(match name.to_ascii_uppercase().as_str() {
+        "OFF" => Level::Off,
+        "ERROR" => Level::Error,
+        "WARN" => Level::Warn,
+        "INFO" => Level::Info,
+        "DEBUG" => Level::Debug,
+        _ => return None,
+      })
+    }
+  }
+
+  // Messages less severe than this are dropped, set from `log_level` in the config or $VIMRS_LOG
+  static THRESHOLD: AtomicU8 = AtomicU8::new(Level::Info as u8);
+
+  pub fn set_threshold(level: Level) {
+    THRESHOLD.store(level as u8, Ordering::Relaxed);
+  }
+
+  // Opened on the first message that passes the threshold, `None` when it couldn't be
+  static FILE: OnceLock<Option<Mutex<File>>> = OnceLock::new();
+
+  // Logging never takes the editor down, a file that can't be written is skipped
   pub fn log(level: String, message: String) {
-    use std::{fs, io::Write};
+    let severity = Level::parse(&level).unwrap_or(Level::Info);
+    if severity == Level::Off || severity as u8 > THRESHOLD.load(Ordering::Relaxed) {
+      return;
+    }
 
     let full_message = format!(
       "{} - {}: {}\n",
-      chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+      chrono::Local::now().format("%Y-%m-%d %H:%M:%S"),
       level,
       message,
     );
 
-    let mut file = fs::OpenOptions::new()
-      .read(true)
-      .append(true)
-      .create(true)
-      .open("vimrs.log")
-      .expect("Unable to open file.");
-
-    file.write_all(full_message.as_bytes()).expect("Unable to write to file.");
+    let file = FILE.get_or_init(|| {
+      fs::OpenOptions::new()
+        .append(true)
+        .create(true)
+        .open("vimrs.log")
+        .ok()
+        .map(Mutex::new)
+    });
+    if let Some(Ok(mut file)) = file.as_ref().map(Mutex::lock) {
+      let _ = file.write_all(full_message.as_bytes());
+    }
   }
 }